@@ -0,0 +1,237 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use config::{Map, Value, ValueKind};
+use regex::Regex;
+
+use super::read_config_table;
+
+/// Table holding the mirror definitions, e.g. `[mirrors.corp]`.
+pub const MIRRORS_KEY: &str = "mirrors";
+/// Rules of a mirror definition applied to the tool download URLs.
+pub const TOOLS_KEY: &str = "tools";
+/// Rules of a mirror definition applied to the ESP-IDF git URLs.
+pub const GIT_KEY: &str = "git";
+
+/// Where the ESP-IDF repository lives, before any mirror is applied.
+pub const IDF_REPOSITORY_URL: &str = "https://github.com/espressif/esp-idf.git";
+/// The tool URLs of `tools.json` and the git URLs all start with it, plain
+/// URL mirrors replace it.
+const GITHUB_URL: &str = "https://github.com";
+
+/// Mirrors of the wizard's list which don't keep the layout of GitHub,
+/// with the prefix of their git URLs.
+const BUILTIN_GIT_MIRRORS: &[(&str, &str, &str)] = &[(
+    "https://gitee.com",
+    "https://github.com/espressif/",
+    "https://gitee.com/EspressifSystems/",
+)];
+
+#[derive(Debug, Clone)]
+enum Pattern {
+    Prefix(String),
+    Regex(Regex),
+}
+
+/// Rewrites URLs starting with a prefix, or matching a regex. The
+/// replacement of a regex can refer to its groups, e.g. `$1`.
+#[derive(Debug, Clone)]
+pub struct RewriteRule {
+    pattern: Pattern,
+    replacement: String,
+}
+
+impl RewriteRule {
+    pub fn prefix(prefix: &str, replacement: &str) -> Self {
+        RewriteRule {
+            pattern: Pattern::Prefix(prefix.to_string()),
+            replacement: replacement.to_string(),
+        }
+    }
+
+    fn apply(&self, url: &str) -> Option<String> {
+        match &self.pattern {
+            Pattern::Prefix(prefix) => url
+                .strip_prefix(prefix.as_str())
+                .map(|rest| format!("{}{}", self.replacement, rest)),
+            Pattern::Regex(regex) => regex
+                .is_match(url)
+                .then(|| regex.replace(url, self.replacement.as_str()).into_owned()),
+        }
+    }
+
+    /// Reads a rule table, `{ prefix = "...", replace = "..." }` or
+    /// `{ regex = "...", replace = "..." }`.
+    fn from_value(value: &Value) -> Result<Self, String> {
+        let ValueKind::Table(rule) = &value.kind else {
+            return Err(
+                "a rule must be a table with `prefix` or `regex` and `replace`".to_string(),
+            );
+        };
+        let string = |key: &str| match rule.get(key).map(|value| &value.kind) {
+            Some(ValueKind::String(s)) => Ok(Some(s.clone())),
+            Some(_) => Err(format!("`{}` must be a string", key)),
+            None => Ok(None),
+        };
+        if let Some(key) = rule
+            .keys()
+            .find(|key| !["prefix", "regex", "replace"].contains(&key.as_str()))
+        {
+            return Err(format!("unknown key `{}` in rule", key));
+        }
+        let replacement = string("replace")?.ok_or("the rule has no `replace`")?;
+        let pattern = match (string("prefix")?, string("regex")?) {
+            (Some(prefix), None) => Pattern::Prefix(prefix),
+            (None, Some(regex)) => Pattern::Regex(
+                Regex::new(&regex).map_err(|err| format!("invalid regex `{}`: {}", regex, err))?,
+            ),
+            _ => return Err("a rule needs either `prefix` or `regex`".to_string()),
+        };
+        Ok(RewriteRule {
+            pattern,
+            replacement,
+        })
+    }
+}
+
+/// Rules tried in order, the first matching one rewrites the URL. URLs no
+/// rule matches are used as they are.
+#[derive(Debug, Clone, Default)]
+pub struct RewriteRules(Vec<RewriteRule>);
+
+impl RewriteRules {
+    pub fn rewrite(&self, url: &str) -> String {
+        self.0
+            .iter()
+            .find_map(|rule| rule.apply(url))
+            .unwrap_or_else(|| url.to_string())
+    }
+
+    fn from_value(value: &Value) -> Result<Self, String> {
+        let ValueKind::Array(rules) = &value.kind else {
+            return Err("expected a list of rules".to_string());
+        };
+        rules
+            .iter()
+            .enumerate()
+            .map(|(index, rule)| {
+                RewriteRule::from_value(rule).map_err(|err| format!("rule {}: {}", index + 1, err))
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(RewriteRules)
+    }
+}
+
+/// A named mirror from the config file.
+#[derive(Debug, Clone, Default)]
+struct MirrorDefinition {
+    tools: RewriteRules,
+    git: RewriteRules,
+}
+
+/// The mirror definitions of the config file. `mirror` and `idf_mirror`
+/// name one of them, or are the URL of a mirror with the layout of GitHub.
+#[derive(Debug, Clone, Default)]
+pub struct Mirrors {
+    definitions: BTreeMap<String, MirrorDefinition>,
+}
+
+impl Mirrors {
+    /// Reads the `[mirrors.<name>]` tables of the raw config file table.
+    /// Errors name the definition and the list they were found in.
+    pub fn from_config_table(table: &Map<String, Value>) -> Result<Self, Vec<(String, String)>> {
+        let Some(value) = table.get(MIRRORS_KEY) else {
+            return Ok(Mirrors::default());
+        };
+        let ValueKind::Table(mirrors) = &value.kind else {
+            return Err(vec![(
+                MIRRORS_KEY.to_string(),
+                "expected a table of mirror definitions".to_string(),
+            )]);
+        };
+
+        let mut definitions = BTreeMap::new();
+        let mut problems = vec![];
+        for (name, mirror) in mirrors {
+            let prefix = format!("{}.{}", MIRRORS_KEY, name);
+            let ValueKind::Table(mirror) = &mirror.kind else {
+                problems.push((
+                    prefix,
+                    "expected a table with `tools` and `git`".to_string(),
+                ));
+                continue;
+            };
+            let mut definition = MirrorDefinition::default();
+            for (key, value) in mirror {
+                let path = format!("{}.{}", prefix, key);
+                let rules = match key.as_str() {
+                    TOOLS_KEY => &mut definition.tools,
+                    GIT_KEY => &mut definition.git,
+                    _ => {
+                        problems.push((path, "unknown key, expected `tools` or `git`".to_string()));
+                        continue;
+                    }
+                };
+                match RewriteRules::from_value(value) {
+                    Ok(parsed) => *rules = parsed,
+                    Err(err) => problems.push((path, err)),
+                }
+            }
+            definitions.insert(name.clone(), definition);
+        }
+
+        if problems.is_empty() {
+            Ok(Mirrors { definitions })
+        } else {
+            problems.sort();
+            Err(problems)
+        }
+    }
+
+    /// Reads the mirror definitions of the config file at `path`, if any.
+    pub fn load(path: Option<&Path>) -> Result<Self, String> {
+        let Some(path) = path else {
+            return Ok(Mirrors::default());
+        };
+        Mirrors::from_config_table(&read_config_table(path)?).map_err(|problems| {
+            problems
+                .iter()
+                .map(|(key, problem)| format!("{}: {}", key, problem))
+                .collect::<Vec<_>>()
+                .join(", ")
+        })
+    }
+
+    pub fn is_defined(&self, name: &str) -> bool {
+        self.definitions.contains_key(name)
+    }
+
+    /// Rules for the tool downloads of the `mirror` setting.
+    pub fn tools(&self, mirror: Option<&str>) -> RewriteRules {
+        match mirror {
+            Some(name) if self.is_defined(name) => self.definitions[name].tools.clone(),
+            Some(url) => RewriteRules(vec![RewriteRule::prefix(GITHUB_URL, url)]),
+            None => RewriteRules::default(),
+        }
+    }
+
+    /// Rules for the ESP-IDF repository of the `idf_mirror` setting.
+    pub fn git(&self, idf_mirror: Option<&str>) -> RewriteRules {
+        let Some(mirror) = idf_mirror else {
+            return RewriteRules::default();
+        };
+        if self.is_defined(mirror) {
+            return self.definitions[mirror].git.clone();
+        }
+        let url = mirror.trim_end_matches('/');
+        match BUILTIN_GIT_MIRRORS
+            .iter()
+            .find(|(builtin, _, _)| *builtin == url)
+        {
+            Some((_, prefix, replacement)) => {
+                RewriteRules(vec![RewriteRule::prefix(prefix, replacement)])
+            }
+            None => RewriteRules(vec![RewriteRule::prefix(GITHUB_URL, url)]),
+        }
+    }
+}
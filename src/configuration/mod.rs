@@ -0,0 +1,102 @@
+pub mod mirrors;
+pub mod profiles;
+pub mod sources;
+pub mod validation;
+
+use std::path::Path;
+
+use config::{Config, File, Map, Source, Value, ValueKind};
+use idf_im_lib::settings::Settings;
+
+use crate::network;
+use sources::SettingsSources;
+use validation::{EIM_KEYS, KNOWN_KEYS};
+
+/// Reads a configuration file into its raw key/value table, without applying
+/// any of the defaults or overrides `Settings::new` layers on top of it.
+pub fn read_config_table(path: &Path) -> Result<Map<String, Value>, String> {
+    Config::builder()
+        .add_source(File::from(path.to_path_buf()))
+        .build()
+        .and_then(|config| config.collect())
+        .map_err(|err| format!("{}: {}", path.display(), err))
+}
+
+/// `value` as a TOML basic string.
+pub fn toml_string(value: &str) -> String {
+    // the escapes of JSON strings are valid in TOML basic strings
+    serde_json::to_string(value).unwrap()
+}
+
+/// The value of a setting eim handles itself, like `python`, taken from the
+/// same sources and with the same precedence as the others.
+pub fn eim_setting(sources: &SettingsSources, key: &str) -> Result<Option<String>, String> {
+    let config_table = match sources.config_path.as_deref() {
+        Some(path) => Some(read_config_table(path)?),
+        None => None,
+    };
+    let profile_values = validation::selected_profile(sources, config_table.as_ref())
+        .map_err(|problem| problem.to_string())?;
+    Ok(sources
+        .value_of(key, config_table.as_ref(), &profile_values)
+        .and_then(|value| value.into_string().ok()))
+}
+
+fn format_value(value: &Value) -> String {
+    match &value.kind {
+        ValueKind::Nil => "<unset>".to_string(),
+        ValueKind::String(s) => format!("{:?}", network::hide_credentials(s)),
+        ValueKind::Array(items) => format!(
+            "[{}]",
+            items
+                .iter()
+                .map(format_value)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        _ => value.to_string(),
+    }
+}
+
+/// Renders the effective settings one `key = value` per line, optionally
+/// followed by the source each value was taken from.
+pub fn describe_settings(
+    settings: &Settings,
+    sources: &SettingsSources,
+    with_origin: bool,
+) -> Result<Vec<String>, String> {
+    let values = Config::try_from(settings)
+        .and_then(|config| config.collect())
+        .map_err(|err| err.to_string())?;
+    let config_table = match sources.config_path.as_deref() {
+        Some(path) => Some(read_config_table(path)?),
+        None => None,
+    };
+    let profile_values = validation::selected_profile(sources, config_table.as_ref())
+        .map_err(|problem| problem.to_string())?;
+
+    Ok(KNOWN_KEYS
+        .iter()
+        .map(|key| {
+            let value = if EIM_KEYS.contains(key) {
+                sources.value_of(key, config_table.as_ref(), &profile_values)
+            } else {
+                values.get(*key).cloned()
+            };
+            let value = value
+                .as_ref()
+                .map(format_value)
+                .unwrap_or_else(|| "<unset>".to_string());
+            if with_origin {
+                format!(
+                    "{} = {}    # {}",
+                    key,
+                    value,
+                    sources.origin_of(key, config_table.as_ref(), &profile_values)
+                )
+            } else {
+                format!("{} = {}", key, value)
+            }
+        })
+        .collect())
+}
@@ -0,0 +1,84 @@
+use config::{Map, Value, ValueKind};
+
+/// Table holding the named profiles, e.g. `[profile.ci]`.
+pub const PROFILE_KEY: &str = "profile";
+/// Key inside a profile naming the profile it extends.
+pub const INHERITS_KEY: &str = "inherits";
+
+fn profiles_table(table: &Map<String, Value>) -> Option<&Map<String, Value>> {
+    match &table.get(PROFILE_KEY)?.kind {
+        ValueKind::Table(profiles) => Some(profiles),
+        _ => None,
+    }
+}
+
+pub fn profile_names(table: &Map<String, Value>) -> Vec<String> {
+    let mut names: Vec<String> = profiles_table(table)
+        .map(|profiles| profiles.keys().cloned().collect())
+        .unwrap_or_default();
+    names.sort();
+    names
+}
+
+fn inherited_profile(profile: &Map<String, Value>) -> Option<String> {
+    match &profile.get(INHERITS_KEY)?.kind {
+        ValueKind::String(parent) => Some(parent.clone()),
+        _ => None,
+    }
+}
+
+/// Collects the settings of profile `name` from the raw config file table,
+/// following `inherits` so that values of a profile override those of the
+/// profile it extends. Each value is returned together with the name of the
+/// profile which defined it.
+pub fn resolve_profile(
+    table: &Map<String, Value>,
+    name: &str,
+) -> Result<Map<String, (String, Value)>, String> {
+    let profiles = profiles_table(table).ok_or_else(|| {
+        format!(
+            "profile `{}` requested but the config file has no [{}] section",
+            name, PROFILE_KEY
+        )
+    })?;
+
+    // walk from the requested profile up to the root, then apply root first
+    let mut chain: Vec<(&str, &Map<String, Value>)> = vec![];
+    let mut current = Some(name.to_string());
+    while let Some(profile_name) = current {
+        if chain.iter().any(|(n, _)| *n == profile_name) {
+            return Err(format!(
+                "profile `{}` inherits from itself through `{}`",
+                name,
+                chain
+                    .iter()
+                    .map(|(n, _)| *n)
+                    .collect::<Vec<_>>()
+                    .join(" -> ")
+            ));
+        }
+        let (key, profile) = profiles.get_key_value(&profile_name).ok_or_else(|| {
+            format!(
+                "unknown profile `{}`, available profiles are: {}",
+                profile_name,
+                profile_names(table).join(", ")
+            )
+        })?;
+        let profile = match &profile.kind {
+            ValueKind::Table(profile) => profile,
+            _ => return Err(format!("profile `{}` must be a table", profile_name)),
+        };
+        chain.push((key.as_str(), profile));
+        current = inherited_profile(profile);
+    }
+
+    let mut resolved = Map::new();
+    for (profile_name, profile) in chain.into_iter().rev() {
+        for (key, value) in profile {
+            if key != INHERITS_KEY {
+                resolved.insert(key.clone(), (profile_name.to_string(), value.clone()));
+            }
+        }
+    }
+    Ok(resolved)
+}
@@ -0,0 +1,178 @@
+use std::fmt;
+use std::path::PathBuf;
+
+use config::{Map, Value};
+
+use super::validation::{KNOWN_KEYS, LIST_KEYS};
+
+/// Prefix of the environment variables which can override any setting,
+/// e.g. `EIM_PATH` or `EIM_IDF_VERSIONS`.
+pub const ENV_PREFIX: &str = "EIM_";
+
+/// Where the effective value of a setting came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Origin {
+    Default,
+    ConfigFile(PathBuf),
+    Profile(String, PathBuf),
+    Environment(String),
+    CommandLine,
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Origin::Default => write!(f, "default"),
+            Origin::ConfigFile(path) => write!(f, "config file {}", path.display()),
+            Origin::Profile(name, path) => {
+                write!(f, "profile `{}` in config file {}", name, path.display())
+            }
+            Origin::Environment(var) => write!(f, "environment variable {}", var),
+            Origin::CommandLine => write!(f, "command line"),
+        }
+    }
+}
+
+pub fn env_var_name(key: &str) -> String {
+    format!("{}{}", ENV_PREFIX, key.to_uppercase())
+}
+
+/// Converts a raw string (from the environment) into the value shape the
+/// setting expects. Lists are comma separated, same as on the command line.
+fn parse_env_value(key: &str, raw: String) -> Value {
+    if LIST_KEYS.contains(&key) {
+        raw.split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .collect::<Vec<&str>>()
+            .into()
+    } else {
+        raw.into()
+    }
+}
+
+/// All the places settings are read from, in order of increasing precedence:
+/// defaults < config file < selected profile < environment (`EIM_*`) < command line.
+#[derive(Debug, Default)]
+pub struct SettingsSources {
+    pub config_path: Option<PathBuf>,
+    pub profile: Option<String>,
+    pub env: Vec<(String, Option<Value>)>,
+    pub cli: Vec<(String, Option<Value>)>,
+}
+
+impl SettingsSources {
+    pub fn new(
+        config_path: Option<PathBuf>,
+        profile: Option<String>,
+        cli_settings: impl IntoIterator<Item = (String, Option<Value>)>,
+    ) -> Self {
+        let env: Vec<(String, Option<Value>)> = KNOWN_KEYS
+            .iter()
+            .map(|key| {
+                let value = std::env::var(env_var_name(key))
+                    .ok()
+                    .map(|raw| parse_env_value(key, raw));
+                (key.to_string(), value)
+            })
+            .collect();
+        let config_path = config_path.or_else(|| {
+            std::env::var(env_var_name("config"))
+                .ok()
+                .map(PathBuf::from)
+        });
+        let profile = profile.or_else(|| std::env::var(env_var_name("profile")).ok());
+
+        SettingsSources {
+            config_path,
+            profile,
+            env,
+            cli: cli_settings.into_iter().collect(),
+        }
+    }
+
+    /// Only the given config file, ignoring environment and command line.
+    pub fn from_config_file(config_path: PathBuf) -> Self {
+        SettingsSources {
+            config_path: Some(config_path),
+            ..Default::default()
+        }
+    }
+
+    fn cli_value(&self, key: &str) -> Option<&Value> {
+        self.cli
+            .iter()
+            .find(|(k, _)| k == key)
+            .and_then(|(_, value)| value.as_ref())
+    }
+
+    fn env_value(&self, key: &str) -> Option<&Value> {
+        self.env
+            .iter()
+            .find(|(k, _)| k == key)
+            .and_then(|(_, value)| value.as_ref())
+    }
+
+    /// Values to be applied on top of the config file: the values of the
+    /// selected profile, then the environment, then the command line.
+    pub fn overrides(
+        &self,
+        profile_values: &Map<String, (String, Value)>,
+    ) -> Vec<(String, Option<Value>)> {
+        let mut overrides: Vec<(String, Option<Value>)> = profile_values
+            .iter()
+            .filter(|(key, _)| self.env_value(key).is_none() && self.cli_value(key).is_none())
+            .map(|(key, (_, value))| (key.clone(), Some(value.clone())))
+            .collect();
+        overrides.extend(
+            self.env
+                .iter()
+                .filter(|(key, value)| value.is_some() && self.cli_value(key).is_none())
+                .cloned(),
+        );
+        overrides.extend(self.cli.iter().cloned());
+        overrides
+    }
+
+    /// The value of `key` from the source with the highest precedence, for
+    /// the settings `Settings` doesn't know about.
+    pub fn value_of(
+        &self,
+        key: &str,
+        config_table: Option<&Map<String, Value>>,
+        profile_values: &Map<String, (String, Value)>,
+    ) -> Option<Value> {
+        self.cli_value(key)
+            .or_else(|| self.env_value(key))
+            .or_else(|| profile_values.get(key).map(|(_, value)| value))
+            .or_else(|| config_table.and_then(|table| table.get(key)))
+            .cloned()
+    }
+
+    /// Tells which source provided the value of `key`. `config_table` is the
+    /// raw content of the config file and `profile_values` the resolved
+    /// profile, if any are used.
+    pub fn origin_of(
+        &self,
+        key: &str,
+        config_table: Option<&Map<String, Value>>,
+        profile_values: &Map<String, (String, Value)>,
+    ) -> Origin {
+        if self.cli_value(key).is_some() {
+            return Origin::CommandLine;
+        }
+        if self.env_value(key).is_some() {
+            return Origin::Environment(env_var_name(key));
+        }
+        let Some(path) = &self.config_path else {
+            return Origin::Default;
+        };
+        if let Some((profile, _)) = profile_values.get(key) {
+            Origin::Profile(profile.clone(), path.clone())
+        } else if config_table.is_some_and(|table| table.contains_key(key)) {
+            Origin::ConfigFile(path.clone())
+        } else {
+            Origin::Default
+        }
+    }
+}
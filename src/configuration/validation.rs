@@ -0,0 +1,334 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use config::{Map, Value, ValueKind};
+use idf_im_lib::settings::Settings;
+use log::{error, warn};
+use rust_i18n::t;
+use url::Url;
+
+use super::mirrors::{Mirrors, MIRRORS_KEY};
+use super::profiles::{resolve_profile, INHERITS_KEY, PROFILE_KEY};
+use super::read_config_table;
+use super::sources::SettingsSources;
+
+use crate::translations;
+
+/// All keys understood by `Settings` and eim. Anything else in a config file
+/// is most likely a typo and would otherwise be silently ignored.
+pub const KNOWN_KEYS: &[&str] = &[
+    "path",
+    "idf_path",
+    "esp_idf_json_path",
+    "tool_download_folder_name",
+    "tool_install_folder_name",
+    "target",
+    "idf_versions",
+    "tools_json_file",
+    "idf_tools_path",
+    "config_file_save_path",
+    "non_interactive",
+    "wizard_all_questions",
+    "mirror",
+    "idf_mirror",
+    "recurse_submodules",
+    "install_all_prerequisites",
+    "idf_features",
+    "python",
+];
+
+/// Keys eim handles itself, which `Settings` doesn't have, see
+/// [`super::eim_setting`].
+pub const EIM_KEYS: &[&str] = &["python"];
+
+const BOOL_KEYS: &[&str] = &[
+    "non_interactive",
+    "wizard_all_questions",
+    "recurse_submodules",
+    "install_all_prerequisites",
+];
+
+pub(crate) const LIST_KEYS: &[&str] = &["target", "idf_versions", "idf_features"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigProblem {
+    pub key: String,
+    pub message: String,
+}
+
+impl ConfigProblem {
+    fn new(key: &str, message: impl Into<String>) -> Self {
+        ConfigProblem {
+            key: key.to_string(),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ConfigProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.key, self.message)
+    }
+}
+
+/// Returns the known key closest to `key`, if any is close enough to be a
+/// plausible typo.
+pub fn suggest_key(key: &str) -> Option<&'static str> {
+    KNOWN_KEYS
+        .iter()
+        .map(|known| (*known, strsim::jaro_winkler(key, known)))
+        .filter(|(_, score)| *score > 0.8)
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(known, _)| known)
+}
+
+fn check_entry(key: &str, path: &str, value: &Value) -> Option<ConfigProblem> {
+    if !KNOWN_KEYS.contains(&key) {
+        let message = match suggest_key(key) {
+            Some(suggestion) => t!(
+                "config.validate.unknown_key_suggestion",
+                suggestion = suggestion
+            ),
+            None => t!("config.validate.unknown_key"),
+        };
+        Some(ConfigProblem::new(path, message))
+    } else if BOOL_KEYS.contains(&key) {
+        (!matches!(value.kind, ValueKind::Boolean(_)))
+            .then(|| ConfigProblem::new(path, t!("config.validate.expected_bool")))
+    } else if LIST_KEYS.contains(&key) {
+        let is_string_list = match &value.kind {
+            ValueKind::Array(items) => items
+                .iter()
+                .all(|item| matches!(item.kind, ValueKind::String(_))),
+            _ => false,
+        };
+        (!is_string_list).then(|| ConfigProblem::new(path, t!("config.validate.expected_list")))
+    } else {
+        (!matches!(value.kind, ValueKind::String(_)))
+            .then(|| ConfigProblem::new(path, t!("config.validate.expected_string")))
+    }
+}
+
+fn sorted_entries(table: &Map<String, Value>) -> Vec<(&String, &Value)> {
+    let mut entries: Vec<(&String, &Value)> = table.iter().collect();
+    entries.sort_by(|a, b| a.0.cmp(b.0));
+    entries
+}
+
+fn check_profiles(table: &Map<String, Value>, profiles: &Value) -> Vec<ConfigProblem> {
+    let profiles = match &profiles.kind {
+        ValueKind::Table(profiles) => profiles,
+        _ => {
+            return vec![ConfigProblem::new(
+                PROFILE_KEY,
+                t!("config.validate.expected_table"),
+            )]
+        }
+    };
+
+    let mut problems = vec![];
+    for (name, profile) in sorted_entries(profiles) {
+        let prefix = format!("{}.{}", PROFILE_KEY, name);
+        let ValueKind::Table(profile) = &profile.kind else {
+            problems.push(ConfigProblem::new(
+                &prefix,
+                t!("config.validate.expected_table"),
+            ));
+            continue;
+        };
+        for (key, value) in sorted_entries(profile) {
+            let path = format!("{}.{}", prefix, key);
+            if key == INHERITS_KEY {
+                if !matches!(value.kind, ValueKind::String(_)) {
+                    problems.push(ConfigProblem::new(
+                        &path,
+                        t!("config.validate.expected_string"),
+                    ));
+                }
+            } else {
+                problems.extend(check_entry(key, &path, value));
+            }
+        }
+        if let Err(err) = resolve_profile(table, name) {
+            problems.push(ConfigProblem::new(&prefix, err));
+        }
+    }
+    problems
+}
+
+/// Checks the raw contents of a config file, including all of its profiles
+/// and mirror definitions, for unknown keys and values of the wrong type.
+pub fn check_config_table(table: &Map<String, Value>) -> Vec<ConfigProblem> {
+    let mut problems = vec![];
+    for (key, value) in sorted_entries(table) {
+        if key == PROFILE_KEY {
+            problems.extend(check_profiles(table, value));
+        } else if key == MIRRORS_KEY {
+            if let Err(errors) = Mirrors::from_config_table(table) {
+                problems.extend(
+                    errors
+                        .iter()
+                        .map(|(path, message)| ConfigProblem::new(path, message.as_str())),
+                );
+            }
+        } else {
+            problems.extend(check_entry(key, key, value));
+        }
+    }
+    problems
+}
+
+/// A mirror is either the name of a mirror definition or a URL.
+fn check_mirror(key: &str, value: &Option<String>, mirrors: &Mirrors) -> Option<ConfigProblem> {
+    let url = value.as_deref()?;
+    if mirrors.is_defined(url) {
+        return None;
+    }
+    match Url::parse(url) {
+        Ok(parsed) if matches!(parsed.scheme(), "http" | "https") && parsed.has_host() => None,
+        _ => Some(ConfigProblem::new(
+            key,
+            t!("config.validate.invalid_url", url = url),
+        )),
+    }
+}
+
+fn check_absolute_path(key: &str, path: Option<PathBuf>) -> Option<ConfigProblem> {
+    let path = path?;
+    if path.is_absolute() {
+        None
+    } else {
+        Some(ConfigProblem::new(
+            key,
+            t!("config.validate.relative_path", path = path.display()),
+        ))
+    }
+}
+
+/// Checks the effective settings (after all sources were merged) for values
+/// which would only make the installation fail later on.
+pub async fn check_settings(settings: &Settings, mirrors: &Mirrors) -> Vec<ConfigProblem> {
+    let mut problems = vec![];
+
+    let targets: Vec<&String> = settings
+        .target
+        .iter()
+        .flatten()
+        .filter(|target| !target.is_empty() && target.as_str() != "all")
+        .collect();
+    if !targets.is_empty() {
+        match idf_im_lib::idf_versions::get_avalible_targets().await {
+            Ok(available) => {
+                for target in targets {
+                    if !available.contains(&target.to_lowercase()) {
+                        problems.push(ConfigProblem::new(
+                            "target",
+                            t!(
+                                "config.validate.unknown_target",
+                                target = target,
+                                available = available.join(", ")
+                            ),
+                        ));
+                    }
+                }
+            }
+            Err(err) => warn!("{}", t!("config.validate.targets_unavailable", e = err)),
+        }
+    }
+
+    problems.extend(check_mirror("mirror", &settings.mirror, mirrors));
+    problems.extend(check_mirror("idf_mirror", &settings.idf_mirror, mirrors));
+    problems.extend(check_absolute_path(
+        "path",
+        settings.path.as_deref().map(idf_im_lib::expand_tilde),
+    ));
+    problems.extend(check_absolute_path(
+        "esp_idf_json_path",
+        settings.esp_idf_json_path.as_ref().map(PathBuf::from),
+    ));
+
+    problems
+}
+
+/// Builds the settings the same way the installer does and validates them,
+/// returning every problem found instead of stopping at the first one.
+pub async fn load_settings(sources: &SettingsSources) -> Result<Settings, Vec<ConfigProblem>> {
+    let mut problems = vec![];
+    let config_table = match sources.config_path.as_deref() {
+        Some(path) => match read_config_table(path) {
+            Ok(table) => {
+                problems.extend(check_config_table(&table));
+                Some(table)
+            }
+            Err(err) => return Err(vec![ConfigProblem::new("config", err)]),
+        },
+        None => None,
+    };
+    let profile_values = match selected_profile(sources, config_table.as_ref()) {
+        Ok(values) => values,
+        Err(problem) => {
+            // a broken inheritance chain is already reported by check_config_table
+            if !problems.iter().any(|p| p.message == problem.message) {
+                problems.push(problem);
+            }
+            return Err(problems);
+        }
+    };
+
+    match Settings::new(
+        sources.config_path.clone(),
+        sources.overrides(&profile_values),
+    ) {
+        Ok(settings) => {
+            // broken definitions are already reported by check_config_table
+            let mirrors = config_table
+                .as_ref()
+                .and_then(|table| Mirrors::from_config_table(table).ok())
+                .unwrap_or_default();
+            problems.extend(check_settings(&settings, &mirrors).await);
+            if problems.is_empty() {
+                return Ok(settings);
+            }
+        }
+        Err(err) => {
+            // type mismatches were already reported above in more detail
+            if problems.is_empty() {
+                problems.push(ConfigProblem::new("config", err.to_string()));
+            }
+        }
+    }
+
+    Err(problems)
+}
+
+/// Resolves the profile selected with `--profile`, if any.
+pub fn selected_profile(
+    sources: &SettingsSources,
+    config_table: Option<&Map<String, Value>>,
+) -> Result<Map<String, (String, Value)>, ConfigProblem> {
+    match (&sources.profile, config_table) {
+        (None, _) => Ok(Map::new()),
+        (Some(name), Some(table)) => {
+            resolve_profile(table, name).map_err(|err| ConfigProblem::new(PROFILE_KEY, err))
+        }
+        (Some(_), None) => Err(ConfigProblem::new(
+            PROFILE_KEY,
+            t!("config.validate.profile_without_config"),
+        )),
+    }
+}
+
+/// Validates a single configuration file, as done by `eim config validate`.
+pub async fn validate_config_file(path: &Path) -> Result<Settings, Vec<ConfigProblem>> {
+    load_settings(&SettingsSources::from_config_file(path.to_path_buf())).await
+}
+
+pub fn log_problems(problems: &[ConfigProblem]) {
+    error!(
+        "{}",
+        translations::plural("config.validate.failed", problems.len())
+    );
+    for problem in problems {
+        error!("  {}", problem);
+    }
+}
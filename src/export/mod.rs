@@ -0,0 +1,313 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use config::{Config, Map, Source, Value, ValueKind};
+use idf_im_lib::settings::Settings;
+use log::{info, warn};
+use rust_i18n::t;
+
+use crate::configuration::mirrors::MIRRORS_KEY;
+use crate::configuration::sources::SettingsSources;
+use crate::configuration::validation::KNOWN_KEYS;
+use crate::configuration::{read_config_table, toml_string};
+use crate::network;
+use crate::packages::{self, Kind};
+
+pub const DEFAULT_BASE_IMAGE: &str = "debian:bookworm-slim";
+/// Where the Dockerfile puts the configuration file of the installation.
+const IMAGE_CONFIG_PATH: &str = "/etc/eim/eim_config.toml";
+/// Home directory of root, who builds the image, for `~` in the settings.
+const IMAGE_HOME: &str = "/root";
+/// Installation path used when the settings have no absolute POSIX one.
+const DEFAULT_INSTALL_PATH: &str = "/root/.espressif";
+/// Settings of the installer run or of the exporting system rather than of
+/// the installation, which are not written to the image.
+const RUN_KEYS: &[&str] = &[
+    "config_file_save_path",
+    "non_interactive",
+    "wizard_all_questions",
+    "install_all_prerequisites",
+    "python",
+];
+/// Packages the Dockerfile needs besides the prerequisites, to download eim
+/// and to run the entrypoint.
+const IMAGE_PACKAGES: &[&str] = &["bash", "ca-certificates", "unzip"];
+const RELEASE_DOWNLOAD_URL: &str = "https://github.com/espressif/idf-im-cli/releases/download";
+const ESP_IDF_EXTENSION: &str = "espressif.esp-idf-extension";
+
+pub struct DockerfileOptions {
+    pub output: PathBuf,
+    pub base_image: String,
+    /// Package manager of the base image, guessed from its name if `None`.
+    pub package_manager: Option<String>,
+    /// Also write a devcontainer.json next to the Dockerfile.
+    pub devcontainer: bool,
+    /// Overwrite existing files.
+    pub force: bool,
+}
+
+/// Writes a Dockerfile installing the prerequisites and the ESP-IDF versions
+/// of the settings, and optionally a devcontainer.json building it. Returns
+/// the paths of the written files.
+pub fn export_dockerfile(
+    settings: &Settings,
+    sources: &SettingsSources,
+    options: &DockerfileOptions,
+) -> Result<Vec<PathBuf>, String> {
+    let kind = match &options.package_manager {
+        Some(name) => Kind::from_name(name),
+        None => Kind::for_image(&options.base_image),
+    }
+    .ok_or_else(|| t!("export.unknown_base_image", image = &options.base_image).to_string())?;
+    let version = settings
+        .idf_versions
+        .as_ref()
+        .and_then(|versions| versions.first())
+        .ok_or_else(|| t!("export.no_version").to_string())?;
+    // the default path is the one of the user exporting
+    let install_path = settings
+        .path
+        .as_ref()
+        .filter(|_| !settings.is_default("path"))
+        .and_then(|path| image_path(&path.to_string_lossy()))
+        .unwrap_or_else(|| DEFAULT_INSTALL_PATH.to_string());
+
+    let config = image_config(settings, sources, &install_path)?;
+    let script = format!("{}/activate_idf_{}.sh", install_path, version);
+    let dockerfile = dockerfile(&options.base_image, kind, &config, &script);
+
+    let devcontainer_path = options
+        .output
+        .parent()
+        .unwrap_or(Path::new(""))
+        .join("devcontainer.json");
+    let mut files = vec![(options.output.clone(), dockerfile)];
+    if options.devcontainer {
+        let dockerfile_name = options
+            .output
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        files.push((devcontainer_path, devcontainer(version, &dockerfile_name)));
+    }
+    if !options.force {
+        if let Some((path, _)) = files.iter().find(|(path, _)| path.exists()) {
+            return Err(t!("export.exists", path = path.display()).to_string());
+        }
+    }
+    for (path, content) in &files {
+        fs::write(path, content)
+            .map_err(|err| format!("Failed to write {}: {}", path.display(), err))?;
+        info!("{}", t!("export.written", path = path.display()));
+    }
+    Ok(files.into_iter().map(|(path, _)| path).collect())
+}
+
+/// `path` in the image, with `~` being the home of root. `None` for paths
+/// which are not absolute POSIX paths, e.g. from Windows.
+fn image_path(path: &str) -> Option<String> {
+    let path = match path.strip_prefix('~') {
+        Some(rest) => format!("{}{}", IMAGE_HOME, rest),
+        None => path.to_string(),
+    };
+    path.starts_with('/').then_some(path)
+}
+
+/// The configuration file of the installation in the image: the effective
+/// settings, the mirror definitions they use and `non_interactive`. Defaults
+/// are left to eim in the image, they may depend on the exporting system.
+fn image_config(
+    settings: &Settings,
+    sources: &SettingsSources,
+    install_path: &str,
+) -> Result<String, String> {
+    let values = Config::try_from(settings)
+        .and_then(|config| config.collect())
+        .map_err(|err| err.to_string())?;
+    let mut lines = vec![];
+    for key in KNOWN_KEYS.iter().filter(|key| !RUN_KEYS.contains(key)) {
+        if *key != "path" && *key != "idf_versions" && settings.is_default(key) {
+            continue;
+        }
+        let value = match *key {
+            "path" => Some(Value::from(install_path)),
+            "esp_idf_json_path" => values
+                .get(*key)
+                .and_then(|value| value.clone().into_string().ok())
+                .and_then(|path| image_path(&path))
+                .map(Value::from),
+            _ => values.get(*key).cloned(),
+        };
+        let Some(value) = value else {
+            continue;
+        };
+        if !matches!(value.kind, ValueKind::Nil) {
+            lines.push(format!("{} = {}", key, toml_value(key, &value)));
+        }
+    }
+    lines.push("non_interactive = true".to_string());
+
+    // mirror definitions used by `mirror` and `idf_mirror`
+    let table = match sources.config_path.as_deref() {
+        Some(path) => read_config_table(path)?,
+        None => Map::new(),
+    };
+    let definitions = match table.get(MIRRORS_KEY).map(|value| &value.kind) {
+        Some(ValueKind::Table(definitions)) => definitions.clone(),
+        _ => Map::new(),
+    };
+    let mut used = [settings.mirror.as_deref(), settings.idf_mirror.as_deref()]
+        .into_iter()
+        .flatten()
+        .filter(|name| definitions.contains_key(*name))
+        .collect::<Vec<_>>();
+    used.dedup();
+    for name in used {
+        lines.push(String::new());
+        lines.push(format!("[{}.{}]", MIRRORS_KEY, toml_key(name)));
+        if let ValueKind::Table(definition) = &definitions[name].kind {
+            for (key, value) in sorted(definition) {
+                let setting = format!("{}.{}.{}", MIRRORS_KEY, name, key);
+                lines.push(format!(
+                    "{} = {}",
+                    toml_key(key),
+                    toml_value(&setting, value)
+                ));
+            }
+        }
+    }
+    Ok(lines.join("\n"))
+}
+
+/// `value` without the credentials of the URLs in it, which would end up in
+/// the image history.
+fn without_credentials(key: &str, value: &str) -> String {
+    let stripped = network::replace_credentials(value, "");
+    if stripped != value {
+        warn!("{}", t!("export.credentials_removed", key = key));
+    }
+    stripped
+}
+
+fn sorted(table: &Map<String, Value>) -> BTreeMap<&String, &Value> {
+    table.iter().collect()
+}
+
+fn toml_key(key: &str) -> String {
+    if !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        key.to_string()
+    } else {
+        toml_string(key)
+    }
+}
+
+/// `value` of the setting `key` in TOML, without credentials.
+fn toml_value(key: &str, value: &Value) -> String {
+    match &value.kind {
+        ValueKind::String(s) => toml_string(&without_credentials(key, s)),
+        ValueKind::Array(items) => format!(
+            "[{}]",
+            items
+                .iter()
+                .map(|item| toml_value(key, item))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        ValueKind::Table(table) => format!(
+            "{{ {} }}",
+            sorted(table)
+                .into_iter()
+                .map(|(name, value)| format!("{} = {}", toml_key(name), toml_value(key, value)))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        _ => value.to_string(),
+    }
+}
+
+fn dockerfile(base_image: &str, kind: Kind, config: &str, script: &str) -> String {
+    let prerequisites = packages::LINUX_PREREQUISITES
+        .iter()
+        .chain(IMAGE_PACKAGES)
+        .collect::<Vec<_>>();
+    let mut install = kind
+        .install_commands(&kind.packages(&prerequisites))
+        .into_iter()
+        .map(|command| command.join(" "))
+        .collect::<Vec<_>>();
+    match kind {
+        Kind::Apt => install.push("rm -rf /var/lib/apt/lists/*".to_string()),
+        // images of Arch come without the package database
+        Kind::Pacman => install.insert(0, "pacman -Syu --noconfirm".to_string()),
+        _ => {}
+    }
+    let eim_version = env!("CARGO_PKG_VERSION");
+    let entrypoint = serde_json::to_string(&[
+        "/bin/bash",
+        "-c",
+        &format!("source {} && exec \"$@\"", script),
+        "--",
+    ])
+    .unwrap();
+
+    let mut lines = vec![
+        "# syntax=docker/dockerfile:1".to_string(),
+        format!(
+            "# Generated by `eim export dockerfile` of eim v{}",
+            eim_version
+        ),
+        format!("FROM {}", base_image),
+        String::new(),
+    ];
+    if kind == Kind::Apt {
+        lines.push("ARG DEBIAN_FRONTEND=noninteractive".to_string());
+    }
+    lines.push(format!("RUN {}", install.join(" && \\\n    ")));
+    lines.push(String::new());
+    lines.push(r#"SHELL ["/bin/bash", "-o", "pipefail", "-c"]"#.to_string());
+    lines.push(String::new());
+    lines.push("ARG TARGETARCH".to_string());
+    lines.push(format!(
+        r#"RUN case "$TARGETARCH" in \
+        amd64) EIM_ARCH=x64 ;; \
+        arm64) EIM_ARCH=arm64 ;; \
+        *) echo "Unsupported architecture: $TARGETARCH" && exit 1 ;; \
+    esac && \
+    wget -q "{url}/v{version}/eim-v{version}-linux-${{EIM_ARCH}}.zip" -O /tmp/eim.zip && \
+    unzip /tmp/eim.zip -d /usr/local/bin && \
+    chmod +x /usr/local/bin/eim && \
+    rm /tmp/eim.zip"#,
+        url = RELEASE_DOWNLOAD_URL,
+        version = eim_version
+    ));
+    lines.push(String::new());
+    lines.push(format!("COPY <<\"EOF\" {}", IMAGE_CONFIG_PATH));
+    lines.push(config.to_string());
+    lines.push("EOF".to_string());
+    lines.push(String::new());
+    lines.push(format!("RUN eim --config {}", IMAGE_CONFIG_PATH));
+    lines.push(String::new());
+    lines.push(format!(
+        "RUN echo 'source {}' >> {}/.bashrc",
+        script, IMAGE_HOME
+    ));
+    lines.push(format!("ENTRYPOINT {}", entrypoint));
+    lines.push(r#"CMD ["/bin/bash"]"#.to_string());
+    lines.join("\n") + "\n"
+}
+
+fn devcontainer(version: &str, dockerfile_name: &str) -> String {
+    let devcontainer = serde_json::json!({
+        "name": format!("ESP-IDF {}", version),
+        "build": { "dockerfile": dockerfile_name },
+        "customizations": {
+            "vscode": { "extensions": [ESP_IDF_EXTENSION] }
+        }
+    });
+    serde_json::to_string_pretty(&devcontainer).unwrap() + "\n"
+}
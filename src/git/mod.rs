@@ -0,0 +1,487 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use git2::build::{CheckoutBuilder, RepoBuilder};
+use git2::{
+    AutotagOption, CheckoutNotificationType, ErrorClass, ErrorCode, FetchOptions, Oid, Repository,
+    Submodule, SubmoduleUpdateOptions,
+};
+use log::{debug, info};
+use rust_i18n::t;
+use url::Url;
+
+use crate::configuration::mirrors::RewriteRules;
+use crate::interrupt;
+use crate::network;
+
+mod progress;
+
+pub use progress::CloneProgress;
+
+/// Fetch options reporting to `progress`, with the proxy for `url` and the
+/// mirror credentials.
+pub fn fetch_options<'a>(progress: &'a CloneProgress, url: &str) -> FetchOptions<'a> {
+    let mut callbacks = network::git_callbacks();
+    callbacks.transfer_progress(|stats| progress.transfer(&stats));
+    let mut options = FetchOptions::new();
+    options.remote_callbacks(callbacks);
+    options.proxy_options(network::git_proxy_options(url));
+    options
+}
+
+/// Checkout options reporting to `progress`. The checkout is aborted
+/// between two files when an interrupt was requested.
+pub fn checkout_options(progress: &CloneProgress) -> CheckoutBuilder<'_> {
+    let mut checkout = CheckoutBuilder::new();
+    checkout.progress(|_, current, total| progress.checkout(current, total));
+    checkout.notify_on(CheckoutNotificationType::UPDATED);
+    checkout.notify(|_, _, _, _, _| !interrupt::requested());
+    checkout
+}
+
+/// Whether the error comes from a transfer or checkout aborted because of an
+/// interrupt, see [`fetch_options`] and [`checkout_options`].
+pub fn is_interrupted(err: &git2::Error) -> bool {
+    err.code() == ErrorCode::User && interrupt::requested()
+}
+
+/// URL of a submodule declared in `.gitmodules`. Relative URLs like
+/// `../../espressif/esptool.git` are relative to the URL of the repository
+/// declaring them, the same as in `git submodule`.
+fn submodule_url(parent_url: &str, declared: &str) -> String {
+    if !declared.starts_with("./") && !declared.starts_with("../") {
+        return declared.to_string();
+    }
+    // the last component of the parent is the first one `../` removes
+    Url::parse(&format!("{}/", parent_url.trim_end_matches('/')))
+        .and_then(|parent| parent.join(declared))
+        .map(String::from)
+        .unwrap_or_else(|_| declared.to_string())
+}
+
+/// Submodules only some targets need, with the targets. The first entry
+/// whose path is the submodule path or one of its parents applies.
+const TARGET_SUBMODULES: &[(&str, &[&str])] = &[
+    ("components/bt/controller/lib_esp32", &["esp32"]),
+    ("components/bt/controller/lib_esp32c2", &["esp32c2"]),
+    (
+        "components/bt/controller/lib_esp32c3_family",
+        &["esp32c3", "esp32s3"],
+    ),
+    ("components/bt/controller/lib_esp32c5", &["esp32c5"]),
+    ("components/bt/controller/lib_esp32c6", &["esp32c6"]),
+    ("components/bt/controller/lib_esp32c61", &["esp32c61"]),
+    ("components/bt/controller/lib_esp32h2", &["esp32h2"]),
+    (
+        "components/bt",
+        &[
+            "esp32", "esp32c2", "esp32c3", "esp32c5", "esp32c6", "esp32c61", "esp32h2", "esp32s3",
+        ],
+    ),
+];
+
+/// Targets [`TARGET_SUBMODULES`] knows about. With any other target, e.g. one
+/// newer than this list, every submodule is cloned.
+const KNOWN_TARGETS: &[&str] = &[
+    "esp32", "esp32c2", "esp32c3", "esp32c5", "esp32c6", "esp32c61", "esp32h2", "esp32p4",
+    "esp32s2", "esp32s3",
+];
+
+/// Whether the submodule at `path` is needed by any of `targets`.
+fn needed_by(path: &str, targets: &[String]) -> bool {
+    if !targets
+        .iter()
+        .all(|target| KNOWN_TARGETS.contains(&target.to_lowercase().as_str()))
+    {
+        return true;
+    }
+    TARGET_SUBMODULES
+        .iter()
+        .find(|(prefix, _)| path == *prefix || path.starts_with(&format!("{}/", prefix)))
+        .is_none_or(|(_, needed)| {
+            targets
+                .iter()
+                .any(|target| needed.contains(&target.to_lowercase().as_str()))
+        })
+}
+
+/// Keeps the submodules none of `targets` needs from being cloned, now and
+/// by `git submodule update` (which the ESP-IDF build runs for submodules
+/// which are not cloned).
+pub fn skip_unused_submodules(repo: &Repository, targets: &[String]) -> Result<(), git2::Error> {
+    let mut config = repo.config()?;
+    for submodule in repo.submodules()? {
+        let path = submodule.path().to_string_lossy().replace('\\', "/");
+        if needed_by(&path, targets) {
+            continue;
+        }
+        info!(
+            "{}",
+            t!(
+                "git.submodule_skipped",
+                path = &path,
+                targets = targets.join(", ")
+            )
+        );
+        config.set_str(
+            &format!("submodule.{}.update", submodule.name().unwrap_or_default()),
+            "none",
+        )?;
+    }
+    Ok(())
+}
+
+/// Whether `submodule.<name>.update` is `none` in the configuration of `repo`.
+fn is_skipped(repo: &Repository, name: &str) -> Result<bool, git2::Error> {
+    match repo
+        .config()?
+        .get_string(&format!("submodule.{}.update", name))
+    {
+        Ok(update) => Ok(update == "none"),
+        Err(err) if err.code() == ErrorCode::NotFound => Ok(false),
+        Err(err) => Err(err),
+    }
+}
+
+/// Sets the URL of origin, adding it if it's missing.
+fn set_origin(repo: &Repository, url: &str) -> Result<(), git2::Error> {
+    match repo.find_remote("origin") {
+        Ok(_) => repo.remote_set_url("origin", url),
+        Err(_) => repo.remote("origin", url).map(|_| ()),
+    }
+}
+
+/// Fetches `commit` into `repo`, with `depth` commits of history. Servers
+/// which don't allow fetching a commit by its id get a fetch of all
+/// branches instead.
+fn fetch_commit(
+    repo: &Repository,
+    url: &str,
+    commit: Oid,
+    depth: Option<u32>,
+    progress: &CloneProgress,
+) -> Result<(), git2::Error> {
+    set_origin(repo, url)?;
+    let mut remote = repo.find_remote("origin")?;
+    let mut options = fetch_options(progress, url);
+    if let Some(depth) = depth {
+        options.depth(depth as i32);
+    }
+    options.download_tags(AutotagOption::None);
+    if let Err(err) = remote.fetch(&[commit.to_string()], Some(&mut options), None) {
+        debug!(
+            "Fetching {} from {} failed, fetching all branches: {}",
+            commit,
+            network::hide_credentials(url),
+            err.message()
+        );
+        remote.fetch(
+            &["+refs/heads/*:refs/remotes/origin/*"],
+            Some(&mut fetch_options(progress, url)),
+            None,
+        )?;
+    }
+    Ok(())
+}
+
+/// Folder of the install path holding the shared git objects.
+const REFERENCE_DIR_NAME: &str = ".eim_git";
+
+/// Bare repository the objects of the versions installed in `install_path`
+/// are fetched into and shared from, so that each version only fetches
+/// what the others don't have. The references of the submodules are in its
+/// `modules` folder, the same layout as the `.git` folder of a clone.
+pub fn reference_dir(install_path: &Path) -> PathBuf {
+    install_path.join(REFERENCE_DIR_NAME).join("esp-idf.git")
+}
+
+fn open_reference(path: &Path, url: &str) -> Result<Repository, git2::Error> {
+    let repo = match Repository::open_bare(path) {
+        Ok(repo) => repo,
+        Err(_) => Repository::init_bare(path)?,
+    };
+    set_origin(&repo, url)?;
+    Ok(repo)
+}
+
+/// Makes the objects of `reference` available to the repository whose git
+/// directory is `git_dir`. It has to be opened again to see them.
+fn add_alternate(git_dir: &Path, reference: &Repository) -> Result<(), git2::Error> {
+    let alternates = git_dir.join("objects").join("info").join("alternates");
+    let objects = reference.path().join("objects");
+    fs::create_dir_all(alternates.parent().unwrap())
+        .and_then(|_| fs::write(&alternates, format!("{}\n", objects.display())))
+        .map_err(|err| {
+            git2::Error::from_str(&format!(
+                "Failed to write {}: {}",
+                alternates.display(),
+                err
+            ))
+        })
+}
+
+/// Clones the submodule, which is not cloned yet, at the commit recorded
+/// in its parent.
+fn clone_submodule(
+    submodule: &mut Submodule,
+    url: &str,
+    depth: Option<u32>,
+    reference: Option<&Path>,
+    progress: &CloneProgress,
+) -> Result<(), git2::Error> {
+    let commit = submodule
+        .index_id()
+        .ok_or_else(|| git2::Error::from_str("the submodule is not in the index"))?;
+    submodule.init(false)?;
+    let submodule_repo = submodule.repo_init(true)?;
+    set_origin(&submodule_repo, url)?;
+    match reference {
+        Some(reference) => {
+            let reference = open_reference(reference, url)?;
+            if reference.find_commit(commit).is_err() {
+                fetch_commit(&reference, url, commit, None, progress)?;
+                // keeps the commit from being pruned from the reference
+                reference.reference(
+                    &format!("refs/eim/{}", commit),
+                    commit,
+                    true,
+                    "submodule commit",
+                )?;
+            } else {
+                debug!("{} is already in {}", commit, reference.path().display());
+            }
+            add_alternate(submodule_repo.path(), &reference)?;
+        }
+        None => fetch_commit(&submodule_repo, url, commit, depth, progress)?,
+    }
+    let submodule_repo = submodule.open()?;
+    submodule_repo.set_head_detached(commit)?;
+    submodule_repo.checkout_head(Some(checkout_options(progress).force()))
+}
+
+/// `git submodule sync && git submodule update --init --recursive`, with
+/// the submodule URLs rewritten by the rules of the mirror. `upstream_url`
+/// is the URL of `repo` before the rules were applied, the URLs of the
+/// submodules are resolved against it. Submodules which are not cloned yet
+/// get `depth` commits of history, or share the objects of the submodule
+/// references in the `reference` repository of `repo`.
+pub fn update_submodules(
+    repo: &Repository,
+    upstream_url: &str,
+    mirror: &RewriteRules,
+    depth: Option<u32>,
+    reference: Option<&Path>,
+    progress: &CloneProgress,
+) -> Result<(), git2::Error> {
+    let mut submodules = vec![];
+    for submodule in repo.submodules()? {
+        if is_skipped(repo, submodule.name().unwrap_or_default())? {
+            debug!("Skipping submodule {}", submodule.path().display());
+        } else {
+            submodules.push(submodule);
+        }
+    }
+    progress.add_submodules(submodules.len());
+    for submodule in submodules {
+        let name = submodule.name().unwrap_or_default().to_string();
+        progress.start_submodule(&submodule.path().to_string_lossy().replace('\\', "/"));
+        let upstream = submodule_url(upstream_url, submodule.url().unwrap_or_default());
+        let url = mirror.rewrite(&upstream);
+        if url != upstream {
+            info!(
+                "{}",
+                t!(
+                    "git.submodule_redirected",
+                    path = submodule.path().display(),
+                    url = network::hide_credentials(&url)
+                )
+            );
+        }
+        debug!("Updating submodule {}", submodule.path().display());
+        // .gitmodules stays as it is, the URL to clone from is taken from
+        // .git/config and an existing clone fetches from its origin
+        repo.config()?
+            .set_str(&format!("submodule.{}.url", name), &url)?;
+        let existing = submodule.open().ok();
+        if let Some(submodule_repo) = &existing {
+            submodule_repo.remote_set_url("origin", &url)?;
+        }
+
+        let mut submodule = repo.find_submodule(&name)?;
+        let submodule_reference = reference.map(|path| path.join("modules").join(&name));
+        if existing.is_none() && (depth.is_some() || submodule_reference.is_some()) {
+            clone_submodule(
+                &mut submodule,
+                &url,
+                depth,
+                submodule_reference.as_deref(),
+                progress,
+            )?;
+        } else {
+            let mut options = SubmoduleUpdateOptions::new();
+            options.fetch(fetch_options(progress, &url));
+            options.checkout(checkout_options(progress));
+            submodule.update(true, Some(&mut options))?;
+        }
+        update_submodules(
+            &submodule.open()?,
+            &upstream,
+            mirror,
+            depth,
+            submodule_reference.as_deref(),
+            progress,
+        )?;
+    }
+    Ok(())
+}
+
+/// What [`clone`] fetches.
+pub struct CloneOptions<'a> {
+    /// Rewrites the URLs of the repository and its submodules.
+    pub mirror: &'a RewriteRules,
+    /// Tag to check out, the default branch if `None`.
+    pub tag: Option<&'a str>,
+    pub recurse_submodules: bool,
+    /// Number of commits of history, all of it if `None`.
+    pub depth: Option<u32>,
+    /// Only clone the submodules these targets need, all if `None`.
+    pub targets: Option<&'a [String]>,
+    /// Reference repository to fetch a tag into and share the objects of,
+    /// see [`reference_dir`]. Not used for shallow clones.
+    pub reference: Option<&'a Path>,
+}
+
+fn ensure_empty(path: &Path) -> Result<(), git2::Error> {
+    if path
+        .read_dir()
+        .is_ok_and(|mut entries| entries.next().is_some())
+    {
+        return Err(git2::Error::new(
+            ErrorCode::Exists,
+            ErrorClass::Invalid,
+            format!("'{}' exists and is not an empty directory", path.display()),
+        ));
+    }
+    Ok(())
+}
+
+/// Fetches only `tag`, with `depth` commits of history, into the new
+/// repository at `path`. A shallow clone can't be made by `RepoBuilder`,
+/// which clones the default branch and follows only the tags in it.
+fn clone_tag_shallow(
+    url: &str,
+    path: &Path,
+    tag: &str,
+    depth: u32,
+    progress: &CloneProgress,
+) -> Result<Repository, git2::Error> {
+    ensure_empty(path)?;
+    let repo = Repository::init(path)?;
+    set_origin(&repo, url)?;
+    {
+        let mut remote = repo.find_remote("origin")?;
+        let mut options = fetch_options(progress, url);
+        options.depth(depth as i32);
+        options.download_tags(AutotagOption::None);
+        remote.fetch(
+            &[format!("+refs/tags/{0}:refs/tags/{0}", tag)],
+            Some(&mut options),
+            None,
+        )?;
+    }
+    Ok(repo)
+}
+
+/// Fetches `tag` into the `reference` repository and creates the
+/// repository at `path` sharing its objects, like `git clone --reference`.
+fn clone_tag_with_reference(
+    url: &str,
+    path: &Path,
+    tag: &str,
+    reference: &Path,
+    progress: &CloneProgress,
+) -> Result<Repository, git2::Error> {
+    ensure_empty(path)?;
+    let reference = open_reference(reference, url)?;
+    let tag_ref = format!("refs/tags/{}", tag);
+    {
+        let mut options = fetch_options(progress, url);
+        options.download_tags(AutotagOption::None);
+        reference.find_remote("origin")?.fetch(
+            &[format!("+{0}:{0}", tag_ref)],
+            Some(&mut options),
+            None,
+        )?;
+    }
+    let target = reference.refname_to_id(&tag_ref)?;
+
+    let repo = Repository::init(path)?;
+    add_alternate(repo.path(), &reference)?;
+    let repo = Repository::open(path)?;
+    set_origin(&repo, url)?;
+    repo.reference(&tag_ref, target, true, "clone")?;
+    Ok(repo)
+}
+
+/// Clones `upstream_url`, rewritten by the rules of the mirror, into `path`
+/// and checks out the tag, or the default branch. Fails with
+/// [`git2::ErrorCode::Exists`] if `path` is not empty.
+pub fn clone(
+    upstream_url: &str,
+    path: &Path,
+    options: &CloneOptions,
+) -> Result<Repository, git2::Error> {
+    let url = options.mirror.rewrite(upstream_url);
+    debug!(
+        "Cloning {} into {}",
+        network::hide_credentials(&url),
+        path.display()
+    );
+    // the reference is only used for tags, which is what gets installed
+    // next to each other
+    let reference = options.reference.filter(|_| options.depth.is_none());
+    let progress = CloneProgress::new();
+    progress.start_repository(
+        &path
+            .file_name()
+            .map_or_else(|| url.clone(), |name| name.to_string_lossy().to_string()),
+    );
+    let repo = match (options.tag, options.depth, reference) {
+        (Some(tag), Some(depth), _) => clone_tag_shallow(&url, path, tag, depth, &progress)?,
+        (Some(tag), None, Some(reference)) => {
+            clone_tag_with_reference(&url, path, tag, reference, &progress)?
+        }
+        (_, depth, _) => {
+            let mut fetch = fetch_options(&progress, &url);
+            if let Some(depth) = depth {
+                fetch.depth(depth as i32);
+            }
+            RepoBuilder::new()
+                .fetch_options(fetch)
+                .with_checkout(checkout_options(&progress))
+                .clone(&url, path)?
+        }
+    };
+    if let Some(tag) = options.tag {
+        let commit = repo
+            .find_reference(&format!("refs/tags/{}", tag))?
+            .peel_to_commit()?;
+        repo.set_head_detached(commit.id())?;
+        repo.checkout_head(Some(checkout_options(&progress).force()))?;
+    }
+    if options.recurse_submodules {
+        if let Some(targets) = options.targets {
+            skip_unused_submodules(&repo, targets)?;
+        }
+        let reference = reference.filter(|_| options.tag.is_some());
+        update_submodules(
+            &repo,
+            upstream_url,
+            options.mirror,
+            options.depth,
+            reference,
+            &progress,
+        )?;
+    }
+    Ok(repo)
+}
@@ -0,0 +1,147 @@
+use std::cell::RefCell;
+use std::time::Instant;
+
+use indicatif::{HumanBytes, ProgressBar, ProgressStyle};
+use rust_i18n::t;
+
+use crate::interrupt;
+
+/// Progress of the fetches and checkouts of a repository and its
+/// submodules, shown on one bar labelled with the repository being fetched
+/// and how many of the submodules were done. The bar is cleared when it is
+/// dropped.
+pub struct CloneProgress {
+    bar: ProgressBar,
+    state: RefCell<State>,
+}
+
+#[derive(Default)]
+struct State {
+    /// Number of the current submodule, 0 while fetching the repository.
+    submodule: usize,
+    /// Submodules found so far, nested ones are added when they are reached.
+    submodules: usize,
+    /// When the current repository started fetching, for the speed.
+    started: Option<Instant>,
+}
+
+impl CloneProgress {
+    pub fn new() -> Self {
+        let bar = ProgressBar::new(1);
+        bar.set_style(
+            ProgressStyle::with_template(
+                "{spinner:.green} [{elapsed_precise}] {prefix:.bold} [{bar:30.cyan/blue}] {msg}",
+            )
+            .unwrap()
+            .progress_chars("#>-"),
+        );
+        CloneProgress {
+            bar,
+            state: RefCell::new(State::default()),
+        }
+    }
+
+    /// Starts fetching the top level repository.
+    pub fn start_repository(&self, name: &str) {
+        self.start(name.to_string());
+    }
+
+    /// Counts `count` more submodules to be fetched.
+    pub fn add_submodules(&self, count: usize) {
+        self.state.borrow_mut().submodules += count;
+    }
+
+    /// Starts fetching the next submodule.
+    pub fn start_submodule(&self, path: &str) {
+        let (current, total) = {
+            let mut state = self.state.borrow_mut();
+            state.submodule += 1;
+            (state.submodule, state.submodules)
+        };
+        self.start(
+            t!(
+                "git.progress.submodule",
+                current = current,
+                total = total,
+                path = path
+            )
+            .to_string(),
+        );
+    }
+
+    fn start(&self, prefix: String) {
+        self.state.borrow_mut().started = Some(Instant::now());
+        self.bar.set_prefix(prefix);
+        self.bar.set_length(1);
+        self.bar.set_position(0);
+        self.bar
+            .set_message(t!("git.progress.connecting").to_string());
+    }
+
+    /// Transfer progress callback of the fetches, aborting them when an
+    /// interrupt was requested.
+    pub fn transfer(&self, stats: &git2::Progress) -> bool {
+        if interrupt::requested() {
+            return false;
+        }
+        if stats.total_objects() == 0 {
+            return true;
+        }
+        if stats.received_objects() < stats.total_objects() {
+            let elapsed = self
+                .state
+                .borrow()
+                .started
+                .map_or(0.0, |started| started.elapsed().as_secs_f64());
+            let speed = if elapsed > 0.0 {
+                (stats.received_bytes() as f64 / elapsed) as u64
+            } else {
+                0
+            };
+            self.bar.set_length(stats.total_objects() as u64);
+            self.bar.set_position(stats.received_objects() as u64);
+            self.bar.set_message(
+                t!(
+                    "git.progress.receiving",
+                    received = stats.received_objects(),
+                    total = stats.total_objects(),
+                    bytes = HumanBytes(stats.received_bytes() as u64),
+                    speed = HumanBytes(speed)
+                )
+                .to_string(),
+            );
+        } else {
+            self.bar.set_length(stats.total_deltas() as u64);
+            self.bar.set_position(stats.indexed_deltas() as u64);
+            self.bar.set_message(
+                t!(
+                    "git.progress.resolving",
+                    indexed = stats.indexed_deltas(),
+                    total = stats.total_deltas()
+                )
+                .to_string(),
+            );
+        }
+        true
+    }
+
+    /// Progress callback of the checkouts.
+    pub fn checkout(&self, current: usize, total: usize) {
+        self.bar.set_length(total as u64);
+        self.bar.set_position(current as u64);
+        self.bar
+            .set_message(t!("git.progress.checkout", current = current, total = total).to_string());
+    }
+}
+
+impl Drop for CloneProgress {
+    fn drop(&mut self) {
+        self.bar.finish_and_clear();
+    }
+}
+
+impl Default for CloneProgress {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,68 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use idf_im_lib::settings::Settings;
+use serde_json::Value;
+
+/// File in `esp_idf_json_path` listing the installed versions for the IDEs.
+pub const IDE_JSON_FILE_NAME: &str = "esp_ide.json";
+
+/// One entry of `idfInstalled`.
+#[derive(Debug, Clone)]
+pub struct Installation {
+    pub name: String,
+    pub path: PathBuf,
+}
+
+pub fn ide_json_path(settings: &Settings) -> PathBuf {
+    PathBuf::from(settings.esp_idf_json_path.clone().unwrap_or_default()).join(IDE_JSON_FILE_NAME)
+}
+
+fn read(path: &Path) -> Result<Value, String> {
+    let content = fs::read_to_string(path)
+        .map_err(|err| format!("Failed to read {}: {}", path.display(), err))?;
+    serde_json::from_str(&content)
+        .map_err(|err| format!("Failed to parse {}: {}", path.display(), err))
+}
+
+/// The installed versions, none if the file does not exist yet.
+pub fn installations(path: &Path) -> Result<Vec<Installation>, String> {
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+    let json = read(path)?;
+    let entries = json["idfInstalled"].as_array().cloned().unwrap_or_default();
+    Ok(entries
+        .iter()
+        .map(|entry| Installation {
+            name: entry["name"].as_str().unwrap_or_default().to_string(),
+            path: PathBuf::from(entry["path"].as_str().unwrap_or_default()),
+        })
+        .collect())
+}
+
+/// Removes the entries of version `name`. If one of them was selected, the
+/// first remaining installation is selected instead. Everything else in the
+/// file is kept as it is.
+pub fn remove_installation(path: &Path, name: &str) -> Result<(), String> {
+    if !path.exists() {
+        return Ok(());
+    }
+    let mut json = read(path)?;
+    let Some(entries) = json["idfInstalled"].as_array_mut() else {
+        return Ok(());
+    };
+    let removed: Vec<Value> = entries
+        .iter()
+        .filter(|entry| entry["name"] == name)
+        .map(|entry| entry["id"].clone())
+        .collect();
+    entries.retain(|entry| entry["name"] != name);
+    let first_id = entries.first().map(|entry| entry["id"].clone());
+    if removed.contains(&json["idfSelectedId"]) {
+        json["idfSelectedId"] = first_id.unwrap_or(Value::String(String::new()));
+    }
+
+    let content = serde_json::to_string_pretty(&json).map_err(|err| err.to_string())?;
+    fs::write(path, content).map_err(|err| format!("Failed to write {}: {}", path.display(), err))
+}
@@ -0,0 +1,82 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use log::warn;
+use rust_i18n::t;
+
+/// Exit code of a process terminated by SIGINT.
+pub const EXIT_CODE: i32 = 130;
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+static INSTALLING: AtomicBool = AtomicBool::new(false);
+
+#[cfg(unix)]
+async fn signals() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut terminate = signal(SignalKind::terminate()).expect("Failed to listen for SIGTERM");
+    loop {
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => on_signal(),
+            _ = terminate.recv() => on_signal(),
+        }
+    }
+}
+
+#[cfg(not(unix))]
+async fn signals() {
+    while tokio::signal::ctrl_c().await.is_ok() {
+        on_signal();
+    }
+}
+
+/// Outside of the installation (e.g. while the wizard asks questions) there
+/// is nothing to clean up, so the process exits right away. During the
+/// installation the first signal asks it to stop after the current step and
+/// the second one exits immediately.
+fn on_signal() {
+    if INTERRUPTED.swap(true, Ordering::SeqCst) || !INSTALLING.load(Ordering::SeqCst) {
+        let _ = console::Term::stdout().show_cursor();
+        std::process::exit(EXIT_CODE);
+    }
+    warn!("{}", t!("interrupt.requested"));
+}
+
+/// Handles SIGINT (Ctrl-C) and SIGTERM for the rest of the run.
+pub fn install_handler() {
+    tokio::spawn(signals());
+}
+
+/// Marks the part of the run which must not be killed half way. Dropping the
+/// guard restores the default behaviour of exiting on the first signal.
+pub struct Installing;
+
+impl Installing {
+    pub fn start() -> Self {
+        INSTALLING.store(true, Ordering::SeqCst);
+        Installing
+    }
+}
+
+impl Drop for Installing {
+    fn drop(&mut self) {
+        INSTALLING.store(false, Ordering::SeqCst);
+    }
+}
+
+pub fn requested() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// Error of an installation stopped by an interrupt.
+pub fn stopped() -> String {
+    t!("interrupt.stopped").to_string()
+}
+
+/// Fails once an interrupt was requested, to be called between steps.
+pub fn check() -> Result<(), String> {
+    if requested() {
+        Err(stopped())
+    } else {
+        Ok(())
+    }
+}
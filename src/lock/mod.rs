@@ -0,0 +1,99 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use fs4::fs_std::FileExt;
+use log::{debug, info};
+use rust_i18n::t;
+
+use crate::interrupt;
+
+/// Lock file created in every directory eim modifies.
+const LOCK_FILE_NAME: &str = ".eim.lock";
+/// How often a waiting run tries the lock again. The lock is polled rather
+/// than waited for, so that the runtime thread (and the signal handler
+/// running on it) isn't blocked.
+const RETRY_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Advisory lock on a directory, held until dropped. It only keeps other eim
+/// processes out, which take the same lock before touching the directory.
+///
+/// The lock file holds the PID of the owner, so that a second run can tell
+/// who it is waiting for. The file itself is never removed, removing it would
+/// let a third process lock a new file while the second one holds the old.
+pub struct DirLock {
+    dir: PathBuf,
+    file: File,
+}
+
+fn owner_pid(file: &mut File) -> String {
+    let mut pid = String::new();
+    // on Windows the locked file can't be read, the PID stays unknown
+    let _ = file.rewind().and_then(|_| file.read_to_string(&mut pid));
+    match pid.trim() {
+        "" => "?".to_string(),
+        pid => pid.to_string(),
+    }
+}
+
+impl DirLock {
+    /// Locks `dir`, creating it if needed. When another process holds the
+    /// lock this fails naming its PID, or waits until it is released if
+    /// `wait` is set.
+    pub async fn acquire(dir: &Path, wait: bool) -> Result<Self, String> {
+        fs::create_dir_all(dir)
+            .map_err(|err| format!("Failed to create {}: {}", dir.display(), err))?;
+        let path = dir.join(LOCK_FILE_NAME);
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)
+            .map_err(|err| format!("Failed to open {}: {}", path.display(), err))?;
+
+        let try_lock = |file: &File| {
+            FileExt::try_lock_exclusive(file)
+                .map_err(|err| format!("Failed to lock {}: {}", path.display(), err))
+        };
+        if !try_lock(&file)? {
+            let pid = owner_pid(&mut file);
+            if !wait {
+                return Err(t!("lock.held", path = dir.display(), pid = pid).to_string());
+            }
+            info!("{}", t!("lock.waiting", path = dir.display(), pid = pid));
+            while !try_lock(&file)? {
+                interrupt::check()?;
+                tokio::time::sleep(RETRY_INTERVAL).await;
+            }
+        }
+
+        file.set_len(0)
+            .and_then(|_| file.rewind())
+            .and_then(|_| write!(file, "{}", std::process::id()))
+            .map_err(|err| format!("Failed to write {}: {}", path.display(), err))?;
+        debug!("Locked {}", dir.display());
+        Ok(DirLock {
+            dir: dir.to_path_buf(),
+            file,
+        })
+    }
+
+    /// Whether `dir` is the locked directory, so it must not be locked again
+    /// (a second lock of the same file blocks even within one process).
+    pub fn covers(&self, dir: &Path) -> bool {
+        match (self.dir.canonicalize(), dir.canonicalize()) {
+            (Ok(locked), Ok(other)) => locked == other,
+            _ => self.dir == dir,
+        }
+    }
+}
+
+impl Drop for DirLock {
+    fn drop(&mut self) {
+        let _ = self.file.set_len(0);
+        let _ = FileExt::unlock(&self.file);
+        debug!("Unlocked {}", self.dir.display());
+    }
+}
@@ -0,0 +1,258 @@
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use clap::ValueEnum;
+use config::ConfigError;
+use idf_im_lib::get_log_directory;
+use log::{debug, LevelFilter};
+use log4rs::{
+    append::{
+        console::{ConsoleAppender, Target},
+        rolling_file::{
+            policy::compound::{
+                roll::{fixed_window::FixedWindowRoller, Roll},
+                trigger::size::SizeTrigger,
+                CompoundPolicy,
+            },
+            RollingFileAppender,
+        },
+    },
+    config::{Appender, Root},
+    encode::{json::JsonEncoder, pattern::PatternEncoder, Encode},
+};
+
+use crate::cli_args::{Cli, Commands};
+
+const LOG_PATTERN: &str = "{d} - {l} - {m}\n";
+/// First message of every run, followed by its ID.
+const RUN_STARTED: &str = "Run ID: ";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    /// Human readable lines, same as the console output
+    Text,
+    /// One JSON object per line, including the run ID and the current
+    /// step, version and tool
+    Json,
+}
+
+pub fn log_file_path(cli: &Cli) -> PathBuf {
+    cli.log_file.clone().map_or_else(
+        || {
+            get_log_directory()
+                .map(|dir| dir.join("eim.log"))
+                .unwrap_or_else(|| {
+                    eprintln!("Failed to get log directory, using default eim.log");
+                    PathBuf::from("eim.log")
+                })
+        },
+        PathBuf::from,
+    )
+}
+
+/// Archives are named after the log file: `eim.log` rolls into `eim.1.log`,
+/// `eim.2.log`, ...
+fn archive_pattern(log_file: &Path) -> String {
+    archived_log_path(log_file, "{}")
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Path of the `index`-th archived log, `1` being the previous run.
+pub fn archived_log_path(log_file: &Path, index: impl Display) -> PathBuf {
+    let stem = log_file
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("eim");
+    let extension = log_file
+        .extension()
+        .and_then(|s| s.to_str())
+        .map(|ext| format!(".{}", ext))
+        .unwrap_or_default();
+    log_file.with_file_name(format!("{}.{}{}", stem, index, extension))
+}
+
+/// Whether the log contains a [`Step`] of an installation or update.
+fn logs_steps(content: &str) -> bool {
+    content.lines().any(|line| {
+        line.split("Step ")
+            .skip(1)
+            .any(|rest| rest.contains(" started") || rest.contains(" finished in"))
+    })
+}
+
+/// The logs of the last run which installed or updated a version, oldest
+/// first: the log file itself, which later runs of other commands like
+/// `eim config show` are appended to, or the archives when another
+/// installation started since. A run rolled over because of its size spans
+/// several files.
+pub fn last_install_logs(log_file: &Path) -> Vec<PathBuf> {
+    let path = |index: usize| match index {
+        0 => log_file.to_path_buf(),
+        _ => archived_log_path(log_file, index),
+    };
+    let read = |index: usize| std::fs::read_to_string(path(index)).ok();
+    let Some(last) = (0..)
+        .map_while(|index| read(index).map(|content| (index, content)))
+        .find(|(_, content)| logs_steps(content))
+        .map(|(index, _)| index)
+    else {
+        return vec![];
+    };
+    // older archives up to the one the run started in
+    let mut first = last;
+    while !read(first).is_some_and(|content| content.contains(RUN_STARTED))
+        && read(first + 1).is_some()
+    {
+        first += 1;
+    }
+    (last..=first).rev().map(path).collect()
+}
+
+/// Splits `log` at the first message of the run `run_id`, into the runs
+/// before it and the run itself.
+pub fn split_at_run<'a>(log: &'a str, run_id: &str) -> (&'a str, &'a str) {
+    match log.find(&format!("{}{}", RUN_STARTED, run_id)) {
+        Some(index) => log.split_at(log[..index].rfind('\n').map_or(0, |line| line + 1)),
+        None => (log, ""),
+    }
+}
+
+/// Whether the run installs or updates versions, which starts a new log.
+fn installs(cli: &Cli) -> bool {
+    matches!(cli.command, None | Some(Commands::Update { .. }))
+}
+
+fn build_roller(log_file: &Path, archives: u32) -> Result<FixedWindowRoller, ConfigError> {
+    FixedWindowRoller::builder()
+        .base(1)
+        .build(&archive_pattern(log_file), archives)
+        .map_err(|e| ConfigError::Message(format!("Failed to build log roller: {}", e)))
+}
+
+/// Sets up console and file logging and returns the ID of this run.
+///
+/// Installations and updates start with a fresh log file: a log left by a
+/// previous run is moved to the archive first. Other commands append to it,
+/// so they don't push the log of the last installation out of the archive.
+/// The file is additionally rolled over when it grows above
+/// `--log-max-size`, keeping at most `--log-archives` old files.
+pub fn setup_logging(cli: &Cli) -> Result<String, ConfigError> {
+    let log_file_name = log_file_path(cli);
+    let run_id = uuid::Uuid::new_v4().simple().to_string();
+
+    if installs(cli) && std::fs::metadata(&log_file_name).is_ok_and(|meta| meta.len() > 0) {
+        if cli.log_archives > 0 {
+            build_roller(&log_file_name, cli.log_archives)?
+                .roll(&log_file_name)
+                .map_err(|e| ConfigError::Message(format!("Failed to archive old log: {}", e)))?;
+        } else {
+            std::fs::remove_file(&log_file_name)
+                .map_err(|e| ConfigError::Message(format!("Failed to remove old log: {}", e)))?;
+        }
+    }
+
+    let file_encoder: Box<dyn Encode> = match cli.log_format {
+        LogFormat::Text => Box::new(PatternEncoder::new(LOG_PATTERN)),
+        LogFormat::Json => Box::new(JsonEncoder::new()),
+    };
+    let policy = CompoundPolicy::new(
+        Box::new(SizeTrigger::new(cli.log_max_size * 1024 * 1024)),
+        Box::new(build_roller(&log_file_name, cli.log_archives.max(1))?),
+    );
+    let logfile = RollingFileAppender::builder()
+        .encoder(file_encoder)
+        .build(&log_file_name, Box::new(policy))
+        .map_err(|e| ConfigError::Message(format!("Failed to build file appender: {}", e)))?;
+
+    // keep stdout clean for output meant for other programs
+    let console_target = match cli.command {
+        Some(Commands::Outdated { json: true, .. }) => Target::Stderr,
+        _ => Target::Stdout,
+    };
+    let stdout = ConsoleAppender::builder()
+        .encoder(Box::new(PatternEncoder::new(LOG_PATTERN)))
+        .target(console_target)
+        .build();
+
+    let log_level = match cli.verbose {
+        0 => LevelFilter::Info,
+        1 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    };
+
+    let config = log4rs::Config::builder()
+        .appender(
+            Appender::builder()
+                .filter(Box::new(log4rs::filter::threshold::ThresholdFilter::new(
+                    LevelFilter::Trace,
+                )))
+                .build("file", Box::new(logfile)),
+        )
+        .appender(
+            Appender::builder()
+                .filter(Box::new(log4rs::filter::threshold::ThresholdFilter::new(
+                    log_level,
+                )))
+                .build("stdout", Box::new(stdout)),
+        )
+        .build(
+            Root::builder()
+                .appender("stdout")
+                .appender("file")
+                .build(LevelFilter::Trace),
+        )
+        .map_err(|e| ConfigError::Message(format!("Failed to build log4rs config: {}", e)))?;
+
+    log4rs::init_config(config)
+        .map_err(|e| ConfigError::Message(format!("Failed to initialize logger: {}", e)))?;
+
+    set_context("run_id", &run_id);
+    debug!("{}{}", RUN_STARTED, run_id);
+    debug!("Logging to: {}", log_file_name.display());
+
+    Ok(run_id)
+}
+
+/// Attaches `key` to every following log record of the current thread (shown
+/// in the JSON log output), e.g. the version being installed.
+pub fn set_context(key: &str, value: &str) {
+    log_mdc::insert(key, value);
+}
+
+pub fn clear_context(key: &str) {
+    log_mdc::remove(key);
+}
+
+/// Marks one step of the installation. While it is alive, records carry the
+/// step name; when dropped it logs how long the step took.
+pub struct Step {
+    name: &'static str,
+    started: Instant,
+}
+
+impl Step {
+    pub fn start(name: &'static str) -> Self {
+        set_context("step", name);
+        debug!("Step {} started", name);
+        Step {
+            name,
+            started: Instant::now(),
+        }
+    }
+}
+
+impl Drop for Step {
+    fn drop(&mut self) {
+        let duration = self.started.elapsed();
+        set_context("duration_ms", &duration.as_millis().to_string());
+        debug!(
+            "Step {} finished in {:.1}s",
+            self.name,
+            duration.as_secs_f64()
+        );
+        clear_context("duration_ms");
+        clear_context("step");
+    }
+}
@@ -1,112 +1,301 @@
-use std::path::PathBuf;
-
 use clap::Parser;
-use config::ConfigError;
-use log::{debug, error, info, LevelFilter};
+use log::{debug, error, info, warn};
 extern crate idf_im_lib;
-use idf_im_lib::get_log_directory;
-use idf_im_lib::settings::Settings;
+use rust_i18n::t;
 mod cli_args;
+mod configuration;
+mod export;
+mod git;
+mod ide_json;
+mod interrupt;
+mod lock;
+mod logging;
+mod network;
+mod outdated;
+mod packages;
+mod python;
+mod report;
+mod self_update;
+mod signatures;
+mod translations;
+mod update;
+mod versions;
 mod wizard;
 
-rust_i18n::i18n!("locales", fallback = "en");
-
-use log4rs::{
-    append::{console::ConsoleAppender, file::FileAppender},
-    config::{Appender, Root},
-    encode::pattern::PatternEncoder,
-};
-
-fn setup_logging(cli: &cli_args::Cli) -> Result<(), config::ConfigError> {
-    let log_file_name = cli.log_file.clone().map_or_else(
-        || {
-            get_log_directory()
-                .map(|dir| dir.join("eim.log"))
-                .unwrap_or_else(|| {
-                    eprintln!("Failed to get log directory, using default eim.log");
-                    PathBuf::from("eim.log")
-                })
-        },
-        PathBuf::from,
-    );
-
-    // ... (rest of the logging setup code)
-    let logfile = FileAppender::builder()
-        .encoder(Box::new(PatternEncoder::new("{d} - {l} - {m}\n")))
-        .build(log_file_name)
-        .map_err(|e| ConfigError::Message(format!("Failed to build file appender: {}", e)))?;
+use configuration::sources::SettingsSources;
 
-    let stdout = ConsoleAppender::builder()
-        .encoder(Box::new(PatternEncoder::new("{d} - {l} - {m}\n")))
-        .build();
+rust_i18n::i18n!(
+    "locales",
+    fallback = "en",
+    backend = translations::UserTranslations::load()
+);
 
-    let log_level = match cli.verbose {
-        0 => LevelFilter::Info,
-        1 => LevelFilter::Debug,
-        _ => LevelFilter::Trace,
+/// Maps a POSIX locale like `zh_CN.UTF-8` to one of the wizard languages.
+fn wizard_locale(posix_locale: &str) -> Option<String> {
+    let language = posix_locale
+        .split(['_', '.', '@', '-'])
+        .next()?
+        .to_lowercase();
+    let locale = match language.as_str() {
+        "zh" => "cn".to_string(),
+        _ => language,
     };
+    rust_i18n::available_locales!()
+        .contains(&locale.as_str())
+        .then_some(locale)
+}
 
-    let config = log4rs::Config::builder()
-        .appender(
-            Appender::builder()
-                .filter(Box::new(log4rs::filter::threshold::ThresholdFilter::new(
-                    LevelFilter::Trace,
-                )))
-                .build("file", Box::new(logfile)),
-        )
-        .appender(
-            Appender::builder()
-                .filter(Box::new(log4rs::filter::threshold::ThresholdFilter::new(
-                    log_level,
-                )))
-                .build("stdout", Box::new(stdout)),
-        )
-        .build(
-            Root::builder()
-                .appender("stdout")
-                .appender("file")
-                .build(LevelFilter::Trace),
-        )
-        .map_err(|e| ConfigError::Message(format!("Failed to build log4rs config: {}", e)))?;
-
-    log4rs::init_config(config)
-        .map_err(|e| ConfigError::Message(format!("Failed to initialize logger: {}", e)))?;
-
-    Ok(())
+/// Locale from the environment, checked in the POSIX order of precedence.
+fn locale_from_env() -> Option<String> {
+    ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|value| !value.is_empty())
+        .and_then(|value| wizard_locale(&value))
 }
 
 fn set_locale(locale: &Option<String>) {
-    match locale {
+    match locale.clone().or_else(locale_from_env) {
         Some(l) => {
-            rust_i18n::set_locale(l);
+            rust_i18n::set_locale(&l);
             info!("Set locale to: {}", l);
         }
         None => debug!("No locale specified, defaulting to en"),
     }
 }
 
-#[tokio::main]
-async fn main() {
-    let cli = cli_args::Cli::parse();
+/// The keys of `--signature-key`, exits when one of them can't be read.
+fn load_trusted_keys(files: &[std::path::PathBuf]) -> signatures::TrustedKeys {
+    signatures::TrustedKeys::load(files).unwrap_or_else(|err| {
+        error!("Error: {}", err);
+        std::process::exit(1);
+    })
+}
+
+/// The mirror definitions of the config file, exits when they are broken.
+fn load_mirrors(sources: &SettingsSources) -> configuration::mirrors::Mirrors {
+    configuration::mirrors::Mirrors::load(sources.config_path.as_deref()).unwrap_or_else(|err| {
+        error!("Error: {}", err);
+        std::process::exit(1);
+    })
+}
+
+/// The configured python interpreter, exits when the config file is broken.
+fn load_python(sources: &SettingsSources) -> Option<String> {
+    configuration::eim_setting(sources, "python").unwrap_or_else(|err| {
+        error!("Error: {}", err);
+        std::process::exit(1);
+    })
+}
 
-    setup_logging(&cli).unwrap();
+async fn run_command(command: cli_args::Commands, cli: cli_args::Cli, run_id: &str) {
+    match command {
+        cli_args::Commands::Config { command } => match command {
+            cli_args::ConfigCommands::Validate { file } => {
+                match configuration::validation::validate_config_file(&file).await {
+                    Ok(_) => info!("{}", t!("config.validate.ok", file = file.display())),
+                    Err(problems) => {
+                        configuration::validation::log_problems(&problems);
+                        std::process::exit(1);
+                    }
+                }
+            }
+            cli_args::ConfigCommands::Show { origin } => {
+                let sources = SettingsSources::new(cli.config.clone(), cli.profile.clone(), cli);
+                let described = match configuration::validation::load_settings(&sources).await {
+                    Ok(settings) => configuration::describe_settings(&settings, &sources, origin),
+                    Err(problems) => {
+                        configuration::validation::log_problems(&problems);
+                        std::process::exit(1);
+                    }
+                };
+                match described {
+                    Ok(lines) => lines.iter().for_each(|line| println!("{}", line)),
+                    Err(err) => {
+                        error!("Error: {}", err);
+                        std::process::exit(1);
+                    }
+                }
+            }
+        },
+        cli_args::Commands::Report { output } => {
+            let log_file = logging::log_file_path(&cli);
+            let sources = SettingsSources::new(cli.config.clone(), cli.profile.clone(), cli);
+            match report::create_report(run_id, &log_file, &sources, output).await {
+                Ok(path) => info!("{}", t!("report.created", path = path.display())),
+                Err(err) => {
+                    error!("Error: {}", err);
+                    std::process::exit(1);
+                }
+            }
+        }
+        cli_args::Commands::Update {
+            version,
+            to,
+            keep_old,
+        } => {
+            let trusted_keys = load_trusted_keys(&cli.signature_key);
+            let wait_for_lock = cli.wait;
+            let clone_depth = cli.clone_depth;
+            let skip_unused_submodules = cli.skip_unused_submodules;
+            let sources = SettingsSources::new(cli.config.clone(), cli.profile.clone(), cli);
+            let settings = match configuration::validation::load_settings(&sources).await {
+                Ok(settings) => settings,
+                Err(problems) => {
+                    configuration::validation::log_problems(&problems);
+                    std::process::exit(1);
+                }
+            };
+            let options = update::UpdateOptions {
+                to,
+                keep_old,
+                wait_for_lock,
+                trusted_keys,
+                mirrors: load_mirrors(&sources),
+                python: load_python(&sources),
+                clone_depth,
+                skip_unused_submodules,
+            };
+            if let Err(err) = update::update_version(settings, &version, &options).await {
+                error!("Error: {}", err);
+                if interrupt::requested() {
+                    std::process::exit(interrupt::EXIT_CODE);
+                }
+                std::process::exit(1);
+            }
+        }
+        cli_args::Commands::Export { command } => match command {
+            cli_args::ExportCommands::Dockerfile {
+                output,
+                base_image,
+                package_manager,
+                devcontainer,
+                force,
+            } => {
+                let sources = SettingsSources::new(cli.config.clone(), cli.profile.clone(), cli);
+                let settings = match configuration::validation::load_settings(&sources).await {
+                    Ok(settings) => settings,
+                    Err(problems) => {
+                        configuration::validation::log_problems(&problems);
+                        std::process::exit(1);
+                    }
+                };
+                let options = export::DockerfileOptions {
+                    output,
+                    base_image,
+                    package_manager,
+                    devcontainer,
+                    force,
+                };
+                if let Err(err) = export::export_dockerfile(&settings, &sources, &options) {
+                    error!("Error: {}", err);
+                    std::process::exit(1);
+                }
+            }
+        },
+        cli_args::Commands::Outdated {
+            json,
+            versions_index,
+        } => {
+            let sources = SettingsSources::new(cli.config.clone(), cli.profile.clone(), cli);
+            let settings = match configuration::validation::load_settings(&sources).await {
+                Ok(settings) => settings,
+                Err(problems) => {
+                    configuration::validation::log_problems(&problems);
+                    std::process::exit(1);
+                }
+            };
+            if let Err(err) =
+                outdated::print_outdated(&settings, versions_index.as_deref(), json).await
+            {
+                error!("Error: {}", err);
+                std::process::exit(1);
+            }
+        }
+        cli_args::Commands::SelfUpdate { check, release_url } => {
+            let options = self_update::SelfUpdateOptions {
+                release_url,
+                check_only: check,
+                trusted_keys: load_trusted_keys(&cli.signature_key),
+            };
+            if let Err(err) = self_update::self_update(&options).await {
+                error!("Error: {}", err);
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+fn main() {
+    let (args, deprecation_warnings) = cli_args::rewrite_legacy_bool_args(std::env::args());
+    let cli = cli_args::Cli::parse_from(args);
+
+    let run_id = logging::setup_logging(&cli).unwrap();
     set_locale(&cli.locale);
+    for warning in deprecation_warnings {
+        warn!("{}", warning);
+    }
+    // sets environment variables, which is only safe while this is the only
+    // thread
+    let network_options = network::NetworkOptions {
+        proxy: cli.proxy.clone(),
+        ca_bundle: cli.ca_bundle.clone(),
+    };
+    if let Err(err) = network::configure(&network_options) {
+        error!("Error: {}", err);
+        std::process::exit(1);
+    }
+
+    tokio::runtime::Runtime::new()
+        .expect("Failed to start the async runtime")
+        .block_on(run(cli, run_id));
+}
 
-    let settings = Settings::new(cli.config.clone(), cli.into_iter());
+/// Runs the subcommand, or else the installation wizard.
+async fn run(mut cli: cli_args::Cli, run_id: String) {
+    interrupt::install_handler();
+    if let Some(command) = cli.command.take() {
+        run_command(command, cli, &run_id).await;
+        return;
+    }
+
+    let mut options = wizard::InstallOptions {
+        keep_failed: cli.keep_failed,
+        ignore_disk_space: cli.ignore_disk_space,
+        wait_for_lock: cli.wait,
+        trusted_keys: load_trusted_keys(&cli.signature_key),
+        mirrors: Default::default(),
+        clone_depth: cli.clone_depth,
+        skip_unused_submodules: cli.skip_unused_submodules,
+        shared_git_objects: cli.shared_git_objects,
+        python: None,
+    };
+    let sources = SettingsSources::new(cli.config.clone(), cli.profile.clone(), cli);
+    let settings = configuration::validation::load_settings(&sources).await;
     // let settings = cli_args::Settings::new();
     match settings {
         Ok(settings) => {
-            let result = wizard::run_wizzard_run(settings).await;
+            options.mirrors = load_mirrors(&sources);
+            options.python = load_python(&sources);
+            let result = wizard::run_wizzard_run(settings, &options).await;
             match result {
                 Ok(r) => {
                     info!("Wizard result: {:?}", r);
-                    println!("Successfully installed IDF");
-                    println!("Now you can start using IDF tools");
+                    println!("{}", t!("wizard.finished.installed"));
+                    println!("{}", t!("wizard.finished.next_steps"));
+                }
+                Err(err) => {
+                    error!("Error: {}", err);
+                    if interrupt::requested() {
+                        std::process::exit(interrupt::EXIT_CODE);
+                    }
                 }
-                Err(err) => error!("Error: {}", err),
             }
         }
-        Err(err) => error!("Error: {}", err),
+        Err(problems) => {
+            configuration::validation::log_problems(&problems);
+            std::process::exit(1);
+        }
     }
 
     // next step is source the env vars
@@ -0,0 +1,268 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use git2::{Cred, CredentialType, ProxyOptions, RemoteCallbacks};
+use log::{debug, warn};
+use rust_i18n::t;
+use url::Url;
+
+/// Proxy given with `--proxy`, the environment is used otherwise.
+static PROXY: OnceLock<String> = OnceLock::new();
+/// Certificates of `--ca-bundle`, trusted by [`http_client`] in addition to
+/// the system ones.
+static CA_CERTIFICATES: OnceLock<Vec<reqwest::Certificate>> = OnceLock::new();
+
+/// Environment variable prefix of the credentials of a mirror, followed by
+/// its host name (`EIM_CREDENTIALS_ARTIFACTORY_EXAMPLE_COM=user:token`).
+const CREDENTIALS_ENV_PREFIX: &str = "EIM_CREDENTIALS_";
+
+pub struct NetworkOptions {
+    pub proxy: Option<String>,
+    pub ca_bundle: Option<PathBuf>,
+}
+
+/// Applies the options to everything eim downloads. The HTTP downloads,
+/// most of them done by idf-im-lib, read the proxy and the CA bundle from
+/// the environment, so they are set there for the whole process. This
+/// changes the environment, so it has to run before the async runtime starts
+/// its threads. The client of [`http_client`] gets them explicitly.
+pub fn configure(options: &NetworkOptions) -> Result<(), String> {
+    if let Some(proxy) = &options.proxy {
+        Url::parse(proxy).map_err(|err| t!("network.invalid_proxy", proxy = proxy, error = err))?;
+        std::env::set_var("HTTPS_PROXY", proxy);
+        std::env::set_var("HTTP_PROXY", proxy);
+        let _ = PROXY.set(proxy.clone());
+        debug!("Using proxy {}", hide_credentials(proxy));
+    }
+    if let Some(ca_bundle) = &options.ca_bundle {
+        if !ca_bundle.is_file() {
+            return Err(t!("network.ca_bundle_missing", path = ca_bundle.display()).to_string());
+        }
+        let certificates = fs::read(ca_bundle)
+            .map_err(|err| err.to_string())
+            .and_then(|pem| {
+                reqwest::Certificate::from_pem_bundle(&pem).map_err(|err| err.to_string())
+            })
+            .map_err(|err| format!("Failed to read {}: {}", ca_bundle.display(), err))?;
+        let _ = CA_CERTIFICATES.set(certificates);
+        // only OpenSSL reads it, on Windows and macOS the downloads of
+        // idf-im-lib use the system store
+        std::env::set_var("SSL_CERT_FILE", ca_bundle);
+        if cfg!(any(windows, target_os = "macos")) {
+            warn!(
+                "{}",
+                t!("network.ca_bundle_system", path = ca_bundle.display())
+            );
+        }
+        // only libgit2 built with OpenSSL reads certificate files, on
+        // Windows and macOS the system store is used
+        if let Err(err) = unsafe { git2::opts::set_ssl_cert_file(ca_bundle) } {
+            warn!(
+                "{}",
+                t!(
+                    "network.ca_bundle_git",
+                    path = ca_bundle.display(),
+                    error = err.message()
+                )
+            );
+        }
+        debug!("Using CA bundle {}", ca_bundle.display());
+    }
+    Ok(())
+}
+
+/// Client for the requests eim makes itself, rather than through
+/// idf-im-lib, with the proxy and the certificates of the options.
+pub fn http_client() -> Result<reqwest::Client, String> {
+    let mut builder =
+        reqwest::Client::builder().user_agent(format!("eim/{}", env!("CARGO_PKG_VERSION")));
+    if let Some(proxy) = PROXY.get() {
+        let proxy = reqwest::Proxy::all(proxy).map_err(|err| err.to_string())?;
+        builder = builder.proxy(proxy.no_proxy(reqwest::NoProxy::from_env()));
+    }
+    for certificate in CA_CERTIFICATES.get().into_iter().flatten() {
+        builder = builder.add_root_certificate(certificate.clone());
+    }
+    builder.build().map_err(|err| err.to_string())
+}
+
+/// `text` with the credentials of the URLs in it hidden, for logs, error
+/// messages and printed settings.
+pub fn hide_credentials(text: &str) -> String {
+    replace_credentials(text, "***")
+}
+
+/// `text` with the user information of the URLs in it, a user and password
+/// or only a token, replaced by `replacement`. An empty one removes it.
+pub fn replace_credentials(text: &str, replacement: &str) -> String {
+    let mut result = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("://") {
+        let (before, after) = rest.split_at(start + 3);
+        result.push_str(before);
+        let end = after
+            .find(|c: char| c == '/' || c == ')' || c == '"' || c.is_whitespace())
+            .unwrap_or(after.len());
+        let (authority, remaining) = after.split_at(end);
+        match authority.rsplit_once('@') {
+            Some((_, host)) if replacement.is_empty() => result.push_str(host),
+            Some((_, host)) => result.push_str(&format!("{}@{}", replacement, host)),
+            None => result.push_str(authority),
+        }
+        rest = remaining;
+    }
+    result.push_str(rest);
+    result
+}
+
+fn netrc_path() -> Option<PathBuf> {
+    if let Ok(path) = std::env::var("NETRC") {
+        return Some(PathBuf::from(path));
+    }
+    let home = dirs::home_dir()?;
+    [".netrc", "_netrc"]
+        .iter()
+        .map(|name| home.join(name))
+        .find(|path| path.exists())
+}
+
+/// Login and password of `host` in a netrc file, or of its `default` entry.
+fn netrc_credentials(path: &Path, host: &str) -> Option<(String, String)> {
+    let content = fs::read_to_string(path).ok()?;
+    let mut tokens = content.split_whitespace();
+    let mut entries: Vec<(Option<String>, Option<String>, Option<String>)> = vec![];
+    while let Some(token) = tokens.next() {
+        match token {
+            "machine" => entries.push((tokens.next().map(str::to_string), None, None)),
+            "default" => entries.push((None, None, None)),
+            "login" | "password" => {
+                let value = tokens.next().map(str::to_string);
+                if let Some(entry) = entries.last_mut() {
+                    if token == "login" {
+                        entry.1 = value;
+                    } else {
+                        entry.2 = value;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    let matching = entries
+        .iter()
+        .find(|(machine, _, _)| machine.as_deref() == Some(host))
+        .or_else(|| entries.iter().find(|(machine, _, _)| machine.is_none()))?;
+    Some((matching.1.clone()?, matching.2.clone()?))
+}
+
+/// Credentials of a mirror, from `EIM_CREDENTIALS_<HOST>` (`user:password`)
+/// or else from the netrc file.
+pub fn credentials(host: &str) -> Option<(String, String)> {
+    let variable = format!(
+        "{}{}",
+        CREDENTIALS_ENV_PREFIX,
+        host.to_uppercase()
+            .replace(|c: char| !c.is_ascii_alphanumeric(), "_")
+    );
+    if let Ok(value) = std::env::var(&variable) {
+        return match value.split_once(':') {
+            Some((user, password)) => Some((user.to_string(), password.to_string())),
+            None => {
+                warn!("{}", t!("network.invalid_credentials", variable = variable));
+                None
+            }
+        };
+    }
+    netrc_credentials(&netrc_path()?, host)
+}
+
+/// `url` with the credentials of its host, for the downloads which only take
+/// a URL. Never log the result, see [`hide_credentials`].
+pub fn authenticated_url(url: &str) -> String {
+    let Ok(mut parsed) = Url::parse(url) else {
+        return url.to_string();
+    };
+    if !parsed.username().is_empty() {
+        return url.to_string();
+    }
+    match parsed.host_str().and_then(credentials) {
+        Some((user, password))
+            if parsed.set_username(&user).is_ok()
+                && parsed.set_password(Some(&password)).is_ok() =>
+        {
+            parsed.to_string()
+        }
+        _ => url.to_string(),
+    }
+}
+
+/// Proxy for git fetching from `url`: the one of `--proxy` unless the host
+/// is in `NO_PROXY`, or else detected from the environment (`HTTPS_PROXY`,
+/// `NO_PROXY`) and the git configuration.
+pub fn git_proxy_options<'a>(url: &str) -> ProxyOptions<'a> {
+    let mut options = ProxyOptions::new();
+    match PROXY.get() {
+        // not detected, the environment holds the proxy of `--proxy` as well
+        Some(_) if bypasses_proxy(url) => {
+            debug!("Not using the proxy for {}", hide_credentials(url))
+        }
+        Some(proxy) => {
+            options.url(proxy);
+        }
+        None => {
+            options.auto();
+        }
+    }
+    options
+}
+
+/// Whether the host of `url` is in `NO_PROXY` (or `no_proxy`): a list of
+/// host names, matching their subdomains as well, or `*` for all hosts.
+fn bypasses_proxy(url: &str) -> bool {
+    let Some(host) = Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_lowercase))
+    else {
+        return false;
+    };
+    let no_proxy = std::env::var("NO_PROXY")
+        .or_else(|_| std::env::var("no_proxy"))
+        .unwrap_or_default();
+    no_proxy
+        .split(',')
+        .map(|entry| entry.trim().to_lowercase())
+        .filter(|entry| !entry.is_empty())
+        .any(|entry| {
+            let domain = entry.split(':').next().unwrap_or_default();
+            let domain = domain.trim_start_matches('.');
+            entry == "*" || host == domain || host.ends_with(&format!(".{}", domain))
+        })
+}
+
+/// Callbacks answering authentication requests of git with the credentials
+/// of the host. They are given once, libgit2 asks again when they are
+/// refused.
+pub fn git_callbacks<'a>() -> RemoteCallbacks<'a> {
+    let mut callbacks = RemoteCallbacks::new();
+    let mut attempted = false;
+    callbacks.credentials(move |url, username_from_url, allowed| {
+        let host = Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string));
+        let found = host.as_deref().and_then(credentials);
+        match found {
+            Some((user, password))
+                if !attempted && allowed.contains(CredentialType::USER_PASS_PLAINTEXT) =>
+            {
+                attempted = true;
+                Cred::userpass_plaintext(username_from_url.unwrap_or(&user), &password)
+            }
+            _ => Err(git2::Error::from_str(&t!(
+                "network.git_auth",
+                url = hide_credentials(url)
+            ))),
+        }
+    });
+    callbacks
+}
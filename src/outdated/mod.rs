@@ -0,0 +1,114 @@
+use std::path::{Path, PathBuf};
+
+use idf_im_lib::settings::Settings;
+use rust_i18n::t;
+use serde_json::{json, Value};
+
+use crate::ide_json::{self, Installation};
+use crate::versions::{self, VersionsIndex};
+
+/// How an installed version compares with the versions index.
+struct VersionReport {
+    version: String,
+    path: PathBuf,
+    /// A newer release of the same minor version.
+    latest_patch: Option<String>,
+    /// The newest release of every newer minor version.
+    newer_releases: Vec<String>,
+    end_of_life: bool,
+}
+
+impl VersionReport {
+    fn new(installation: &Installation, index: &VersionsIndex) -> Self {
+        let version = installation.name.as_str();
+        let releases = || index.releases.iter().map(String::as_str);
+        VersionReport {
+            version: version.to_string(),
+            path: installation.path.clone(),
+            latest_patch: versions::newest_patch(version, releases()).filter(|latest| {
+                versions::release_number(latest) > versions::release_number(version)
+            }),
+            newer_releases: versions::newer_minors(version, releases()),
+            end_of_life: index.is_end_of_life(version),
+        }
+    }
+
+    /// `unknown` for branches like master, which are not releases.
+    fn status(&self) -> &'static str {
+        if versions::release_number(&self.version).is_none() {
+            "unknown"
+        } else if self.end_of_life {
+            "end_of_life"
+        } else if self.latest_patch.is_some() {
+            "outdated"
+        } else {
+            "up_to_date"
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "version": self.version,
+            "path": self.path,
+            "status": self.status(),
+            "latest_patch": self.latest_patch,
+            "newer_releases": self.newer_releases,
+            "end_of_life": self.end_of_life,
+        })
+    }
+
+    fn to_text(&self) -> String {
+        let mut line = match self.status() {
+            "unknown" => t!("outdated.not_release", version = &self.version),
+            "end_of_life" => t!("outdated.end_of_life", version = &self.version),
+            "outdated" => t!(
+                "outdated.patch",
+                version = &self.version,
+                latest = self.latest_patch.as_deref().unwrap_or_default()
+            ),
+            _ => t!("outdated.up_to_date", version = &self.version),
+        }
+        .to_string();
+        if !self.newer_releases.is_empty() {
+            line.push_str(&format!(
+                ". {}",
+                t!(
+                    "outdated.newer_releases",
+                    releases = self.newer_releases.join(", ")
+                )
+            ));
+        }
+        line
+    }
+}
+
+/// Prints how the versions listed in esp_ide.json compare with the online
+/// versions index, or `versions_index` in its stead.
+pub async fn print_outdated(
+    settings: &Settings,
+    versions_index: Option<&Path>,
+    json: bool,
+) -> Result<(), String> {
+    let installations = ide_json::installations(&ide_json::ide_json_path(settings))?;
+    let index = match versions_index {
+        Some(path) => VersionsIndex::from_file(path)?,
+        None => VersionsIndex::online().await?,
+    };
+    let reports: Vec<VersionReport> = installations
+        .iter()
+        .map(|installation| VersionReport::new(installation, &index))
+        .collect();
+
+    if json {
+        let reports: Vec<Value> = reports.iter().map(VersionReport::to_json).collect();
+        let output = serde_json::to_string_pretty(&reports).map_err(|err| err.to_string())?;
+        println!("{}", output);
+    } else if reports.is_empty() {
+        println!("{}", t!("outdated.none"));
+    } else {
+        reports
+            .iter()
+            .for_each(|report| println!("{}", report.to_text()));
+    }
+    Ok(())
+}
@@ -0,0 +1,451 @@
+use std::cell::OnceCell;
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use log::{debug, warn};
+use rust_i18n::t;
+
+/// Files describing the distribution, see os-release(5).
+const OS_RELEASE_FILES: &[&str] = &["/etc/os-release", "/usr/lib/os-release"];
+/// Reads the distribution from this file instead of the os-release files.
+const OS_RELEASE_ENV: &str = "EIM_OS_RELEASE";
+/// Appends the install commands to this file instead of running them, see
+/// [`FakePackageManager`].
+const FAKE_PACKAGE_MANAGER_ENV: &str = "EIM_FAKE_PACKAGE_MANAGER";
+
+/// Linux distribution, from the os-release file.
+#[derive(Debug, Clone, Default)]
+pub struct Distribution {
+    pub id: String,
+    /// Distributions this one is derived from, e.g. `debian` for Ubuntu.
+    pub id_like: Vec<String>,
+    pub name: String,
+}
+
+impl Distribution {
+    pub fn detect() -> Option<Self> {
+        let files = match env::var_os(OS_RELEASE_ENV) {
+            Some(path) => {
+                warn!(
+                    "{}",
+                    t!(
+                        "prerequisites.test_hook",
+                        variable = OS_RELEASE_ENV,
+                        path = Path::new(&path).display()
+                    )
+                );
+                vec![PathBuf::from(path)]
+            }
+            None => OS_RELEASE_FILES.iter().map(PathBuf::from).collect(),
+        };
+        let content = files
+            .iter()
+            .find_map(|path| fs::read_to_string(path).ok())?;
+        Some(Distribution::parse(&content))
+    }
+
+    fn parse(content: &str) -> Self {
+        let mut distribution = Distribution::default();
+        for line in content.lines() {
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let value = value.trim().trim_matches('"').trim_matches('\'');
+            match key.trim() {
+                "ID" => distribution.id = value.to_lowercase(),
+                "ID_LIKE" => {
+                    distribution.id_like = value.split_whitespace().map(str::to_lowercase).collect()
+                }
+                "PRETTY_NAME" => distribution.name = value.to_string(),
+                "NAME" if distribution.name.is_empty() => distribution.name = value.to_string(),
+                _ => {}
+            }
+        }
+        if distribution.name.is_empty() {
+            distribution.name = distribution.id.clone();
+        }
+        distribution
+    }
+}
+
+/// The package managers prerequisites can be installed with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Apt,
+    Dnf,
+    Pacman,
+    Zypper,
+    Apk,
+}
+
+/// Distribution ids, also matched against `ID_LIKE`, and their package
+/// manager.
+const FAMILIES: &[(&str, Kind)] = &[
+    ("debian", Kind::Apt),
+    ("ubuntu", Kind::Apt),
+    ("fedora", Kind::Dnf),
+    ("rhel", Kind::Dnf),
+    ("centos", Kind::Dnf),
+    ("arch", Kind::Pacman),
+    ("archlinux", Kind::Pacman),
+    ("suse", Kind::Zypper),
+    ("opensuse", Kind::Zypper),
+    ("alpine", Kind::Apk),
+];
+
+/// The prerequisites of ESP-IDF on Linux, as Debian packages, for images
+/// built on any system. idf-im-lib only lists the ones of the system it runs
+/// on, keep this in line with its Linux list and the Python packages.
+pub const LINUX_PREREQUISITES: &[&str] = &[
+    "git",
+    "cmake",
+    "ninja",
+    "wget",
+    "flex",
+    "bison",
+    "gperf",
+    "ccache",
+    "libffi-dev",
+    "libssl-dev",
+    "dfu-util",
+    "libusb-1.0-0",
+    "python3",
+    "python3-pip",
+    "python3-venv",
+];
+
+/// Packages of the prerequisites for apt, dnf, pacman, zypper and apk, in
+/// the order of [`Kind`]. The prerequisites are named as the Debian
+/// packages. `None` where the prerequisite is part of another package.
+const PACKAGES: &[(&str, [Option<&str>; 5])] = &[
+    (
+        "ninja",
+        [
+            Some("ninja-build"),
+            Some("ninja-build"),
+            Some("ninja"),
+            Some("ninja"),
+            Some("samurai"),
+        ],
+    ),
+    (
+        "libffi-dev",
+        [
+            Some("libffi-dev"),
+            Some("libffi-devel"),
+            Some("libffi"),
+            Some("libffi-devel"),
+            Some("libffi-dev"),
+        ],
+    ),
+    (
+        "libssl-dev",
+        [
+            Some("libssl-dev"),
+            Some("openssl-devel"),
+            Some("openssl"),
+            Some("libopenssl-devel"),
+            Some("openssl-dev"),
+        ],
+    ),
+    (
+        "libusb-1.0-0",
+        [
+            Some("libusb-1.0-0"),
+            Some("libusb1"),
+            Some("libusb"),
+            Some("libusb-1_0-0"),
+            Some("libusb"),
+        ],
+    ),
+    (
+        "python3",
+        [
+            Some("python3"),
+            Some("python3"),
+            Some("python"),
+            Some("python3"),
+            Some("python3"),
+        ],
+    ),
+    (
+        "python3-pip",
+        [
+            Some("python3-pip"),
+            Some("python3-pip"),
+            Some("python-pip"),
+            Some("python3-pip"),
+            Some("py3-pip"),
+        ],
+    ),
+    (
+        "python3-venv",
+        [Some("python3-venv"), None, None, None, None],
+    ),
+];
+
+impl Kind {
+    pub const ALL: [Kind; 5] = [Kind::Apt, Kind::Dnf, Kind::Pacman, Kind::Zypper, Kind::Apk];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Kind::Apt => "apt",
+            Kind::Dnf => "dnf",
+            Kind::Pacman => "pacman",
+            Kind::Zypper => "zypper",
+            Kind::Apk => "apk",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Kind::ALL.into_iter().find(|kind| kind.name() == name)
+    }
+
+    /// The package manager of a container image like `debian:bookworm` or
+    /// `opensuse/leap:15.6`, guessed from the parts of its name.
+    pub fn for_image(image: &str) -> Option<Self> {
+        let name = image.split('@').next().unwrap_or_default();
+        // the tag, but not the port of a registry
+        let name = match name.rsplit_once(':') {
+            Some((name, tag)) if !tag.contains('/') => name,
+            _ => name,
+        };
+        name.split(['/', '-', '.']).rev().find_map(|part| {
+            Kind::for_distribution(&Distribution {
+                id: part.to_lowercase(),
+                ..Default::default()
+            })
+        })
+    }
+
+    /// The package manager of the distribution, or of the one it is derived
+    /// from.
+    pub fn for_distribution(distribution: &Distribution) -> Option<Self> {
+        std::iter::once(&distribution.id)
+            .chain(&distribution.id_like)
+            .find_map(|id| {
+                FAMILIES
+                    .iter()
+                    .find(|(family, _)| family == id)
+                    .map(|(_, kind)| *kind)
+            })
+    }
+
+    /// Package providing `prerequisite`, `None` if it comes with another one.
+    fn package(self, prerequisite: &str) -> Option<String> {
+        match PACKAGES.iter().find(|(name, _)| *name == prerequisite) {
+            Some((_, packages)) => packages[self as usize].map(str::to_string),
+            None => Some(prerequisite.to_string()),
+        }
+    }
+
+    /// Packages providing `prerequisites`, each once.
+    pub fn packages<S: AsRef<str>>(self, prerequisites: &[S]) -> Vec<String> {
+        let mut packages = vec![];
+        for package in prerequisites
+            .iter()
+            .filter_map(|prerequisite| self.package(prerequisite.as_ref()))
+        {
+            if !packages.contains(&package) {
+                packages.push(package);
+            }
+        }
+        packages
+    }
+
+    /// Commands installing `packages`, without `sudo`. None when there is
+    /// nothing to install.
+    pub fn install_commands(self, packages: &[String]) -> Vec<Vec<String>> {
+        if packages.is_empty() {
+            return vec![];
+        }
+        let command = |args: &[&str]| {
+            args.iter()
+                .map(|arg| arg.to_string())
+                .chain(packages.iter().cloned())
+                .collect::<Vec<_>>()
+        };
+        match self {
+            Kind::Apt => vec![
+                vec!["apt-get".to_string(), "update".to_string()],
+                command(&["apt-get", "install", "-y"]),
+            ],
+            Kind::Dnf => vec![command(&["dnf", "install", "-y"])],
+            Kind::Pacman => vec![command(&["pacman", "-S", "--needed", "--noconfirm"])],
+            Kind::Zypper => vec![command(&["zypper", "--non-interactive", "install"])],
+            Kind::Apk => vec![command(&["apk", "add", "--no-cache"])],
+        }
+    }
+}
+
+/// How this process can run the install commands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Privileges {
+    Root,
+    /// With `sudo`, which doesn't ask for a password.
+    Sudo,
+    /// Only with `sudo` asking for a password, which is left to the user.
+    None,
+}
+
+/// Installs the packages of a distribution. [`SystemPackageManager`] runs
+/// the commands, [`FakePackageManager`] only records them.
+pub trait PackageManager {
+    fn distribution(&self) -> &Distribution;
+    fn kind(&self) -> Kind;
+    fn privileges(&self) -> Privileges;
+    fn run(&self, command: &[String]) -> Result<(), String>;
+
+    /// Commands installing the packages of `prerequisites`, with `sudo`
+    /// unless running as root.
+    fn install_commands(&self, prerequisites: &[String]) -> Vec<Vec<String>> {
+        let packages = self.kind().packages(prerequisites);
+        let sudo = self.privileges() != Privileges::Root;
+        self.kind()
+            .install_commands(&packages)
+            .into_iter()
+            .map(|command| {
+                if sudo {
+                    std::iter::once("sudo".to_string()).chain(command).collect()
+                } else {
+                    command
+                }
+            })
+            .collect()
+    }
+}
+
+pub struct SystemPackageManager {
+    distribution: Distribution,
+    kind: Kind,
+    /// Checked on first use, `sudo` is only asked once.
+    privileges: OnceCell<Privileges>,
+}
+
+#[cfg(unix)]
+fn is_root() -> bool {
+    // SAFETY: geteuid has no preconditions and cannot fail
+    unsafe { libc::geteuid() == 0 }
+}
+
+#[cfg(not(unix))]
+fn is_root() -> bool {
+    false
+}
+
+impl PackageManager for SystemPackageManager {
+    fn distribution(&self) -> &Distribution {
+        &self.distribution
+    }
+
+    fn kind(&self) -> Kind {
+        self.kind
+    }
+
+    fn privileges(&self) -> Privileges {
+        *self.privileges.get_or_init(|| {
+            if is_root() {
+                Privileges::Root
+            } else if Command::new("sudo")
+                .args(["-n", "true"])
+                .stdin(Stdio::null())
+                .output()
+                .is_ok_and(|output| output.status.success())
+            {
+                Privileges::Sudo
+            } else {
+                Privileges::None
+            }
+        })
+    }
+
+    fn run(&self, command: &[String]) -> Result<(), String> {
+        debug!("Running {:?}", command);
+        let status = Command::new(&command[0])
+            .args(&command[1..])
+            .status()
+            .map_err(|err| format!("{}: {}", command[0], err))?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err(format!("{} exited with {}", command.join(" "), status))
+        }
+    }
+}
+
+/// Appends the commands to a file instead of running them, so the
+/// installation can be tested without changing the system. Used when
+/// `EIM_FAKE_PACKAGE_MANAGER` names the file.
+pub struct FakePackageManager {
+    distribution: Distribution,
+    kind: Kind,
+    log: PathBuf,
+}
+
+impl PackageManager for FakePackageManager {
+    fn distribution(&self) -> &Distribution {
+        &self.distribution
+    }
+
+    fn kind(&self) -> Kind {
+        self.kind
+    }
+
+    fn privileges(&self) -> Privileges {
+        Privileges::Root
+    }
+
+    fn run(&self, command: &[String]) -> Result<(), String> {
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.log)
+            .and_then(|mut log| writeln!(log, "{}", command.join(" ")))
+            .map_err(|err| format!("Failed to write {}: {}", self.log.display(), err))
+    }
+}
+
+/// The package manager of this Linux distribution, `None` if the
+/// distribution is not known.
+pub fn detect() -> Option<Box<dyn PackageManager>> {
+    let distribution = Distribution::detect()?;
+    let Some(kind) = Kind::for_distribution(&distribution) else {
+        debug!("No package manager known for {:?}", distribution);
+        return None;
+    };
+    debug!("{} uses {:?}", distribution.name, kind);
+    Some(match env::var_os(FAKE_PACKAGE_MANAGER_ENV) {
+        Some(log) => {
+            warn!(
+                "{}",
+                t!(
+                    "prerequisites.test_hook",
+                    variable = FAKE_PACKAGE_MANAGER_ENV,
+                    path = Path::new(&log).display()
+                )
+            );
+            Box::new(FakePackageManager {
+                distribution,
+                kind,
+                log: PathBuf::from(log),
+            })
+        }
+        None => Box::new(SystemPackageManager {
+            distribution,
+            kind,
+            privileges: OnceCell::new(),
+        }),
+    })
+}
+
+/// The commands as they would be typed in a shell.
+pub fn command_line(commands: &[Vec<String>]) -> String {
+    commands
+        .iter()
+        .map(|command| command.join(" "))
+        .collect::<Vec<_>>()
+        .join(" && ")
+}
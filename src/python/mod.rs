@@ -0,0 +1,247 @@
+use std::collections::HashSet;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use log::debug;
+use rust_i18n::t;
+
+/// The oldest Python the supported ESP-IDF versions run with.
+pub const MIN_VERSION: (u32, u32) = (3, 9);
+/// Folder of the python environments in the tools folder.
+pub const ENV_FOLDER: &str = "python_env";
+/// Folder in the tools folder with the `python3` link to the selected
+/// interpreter, which idf_tools.py is run with.
+const SHIM_FOLDER: &str = "eim_python";
+
+/// A Python interpreter found on this system.
+#[derive(Debug, Clone)]
+pub struct Interpreter {
+    pub path: PathBuf,
+    /// `(major, minor, patch)`, `None` if `--version` failed.
+    pub version: Option<(u32, u32, u32)>,
+}
+
+impl Interpreter {
+    /// Asks the interpreter at `path` for its version.
+    pub fn probe(path: &Path) -> Self {
+        let version = Command::new(path)
+            .arg("--version")
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .and_then(|output| {
+                // before 3.4 the version was printed to stderr
+                let text = format!(
+                    "{}{}",
+                    String::from_utf8_lossy(&output.stdout),
+                    String::from_utf8_lossy(&output.stderr)
+                );
+                parse_version(&text)
+            });
+        Interpreter {
+            path: path.to_path_buf(),
+            version,
+        }
+    }
+
+    pub fn is_supported(&self) -> bool {
+        self.version
+            .is_some_and(|(major, minor, _)| (major, minor) >= MIN_VERSION)
+    }
+
+    pub fn path_str(&self) -> String {
+        self.path.to_string_lossy().to_string()
+    }
+}
+
+impl fmt::Display for Interpreter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.version {
+            Some((major, minor, patch)) => write!(
+                f,
+                "{} (Python {}.{}.{})",
+                self.path.display(),
+                major,
+                minor,
+                patch
+            ),
+            None => write!(f, "{} (unknown version)", self.path.display()),
+        }
+    }
+}
+
+/// Parses the output of `python --version`, e.g. `Python 3.12.3` or
+/// `Python 3.13.0rc1`.
+fn parse_version(text: &str) -> Option<(u32, u32, u32)> {
+    let version = text.trim().strip_prefix("Python ")?;
+    let mut parts = version.split('.').map(|part| {
+        part.chars()
+            .take_while(char::is_ascii_digit)
+            .collect::<String>()
+            .parse::<u32>()
+            .ok()
+    });
+    let major = parts.next()??;
+    let minor = parts.next()??;
+    let patch = parts.next().flatten().unwrap_or(0);
+    Some((major, minor, patch))
+}
+
+/// File names of interpreters: `python3` and `python3.N`, or `python.exe`
+/// and `python3.exe` on Windows.
+fn is_interpreter_name(name: &str) -> bool {
+    if cfg!(windows) {
+        name.eq_ignore_ascii_case("python.exe") || name.eq_ignore_ascii_case("python3.exe")
+    } else {
+        name == "python3"
+            || name
+                .strip_prefix("python3.")
+                .is_some_and(|minor| !minor.is_empty() && minor.chars().all(|c| c.is_ascii_digit()))
+    }
+}
+
+/// Directories which only hold launchers of other interpreters: the shims of
+/// pyenv, whose versions are searched directly, and the aliases of the
+/// Microsoft Store, which open the store instead of running Python.
+fn is_launcher_dir(dir: &Path) -> bool {
+    dir.components().any(|component| {
+        let name = component.as_os_str().to_string_lossy();
+        name == "shims" || name.eq_ignore_ascii_case("WindowsApps")
+    })
+}
+
+/// The directories of PATH, then the usual install locations which may not
+/// be in it.
+fn candidate_dirs() -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = env::var_os("PATH")
+        .map(|path| env::split_paths(&path).collect())
+        .unwrap_or_default();
+    let pyenv_root = env::var_os("PYENV_ROOT")
+        .map(PathBuf::from)
+        .or_else(|| dirs::home_dir().map(|home| home.join(".pyenv")));
+    if let Some(versions) = pyenv_root.map(|root| root.join("versions")) {
+        dirs.extend(subdirs(&versions).into_iter().map(|version| {
+            if cfg!(windows) {
+                version
+            } else {
+                version.join("bin")
+            }
+        }));
+    }
+    if cfg!(windows) {
+        if let Some(scoop) = idf_im_lib::system_dependencies::get_scoop_path() {
+            dirs.push(PathBuf::from(scoop));
+        }
+        if let Some(local) = dirs::data_local_dir() {
+            dirs.extend(subdirs(&local.join("Programs").join("Python")));
+        }
+    } else {
+        dirs.extend(
+            ["/usr/local/bin", "/usr/bin", "/opt/homebrew/bin"]
+                .iter()
+                .map(PathBuf::from),
+        );
+    }
+    dirs
+}
+
+fn subdirs(path: &Path) -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = fs::read_dir(path)
+        .map(|entries| {
+            entries
+                .filter_map(Result::ok)
+                .map(|entry| entry.path())
+                .filter(|path| path.is_dir())
+                .collect()
+        })
+        .unwrap_or_default();
+    dirs.sort();
+    dirs
+}
+
+/// Interpreters in PATH and in the usual install locations, in this order,
+/// so the first one is what `python3` runs. Interpreters reachable under
+/// several names are listed once.
+pub fn discover() -> Vec<Interpreter> {
+    let mut seen = HashSet::new();
+    let mut found = vec![];
+    for dir in candidate_dirs() {
+        if is_launcher_dir(&dir) {
+            continue;
+        }
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        let mut paths: Vec<PathBuf> = entries
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.file_name()
+                    .is_some_and(|name| is_interpreter_name(&name.to_string_lossy()))
+                    && path.is_file()
+            })
+            .collect();
+        // python3 before python3.N
+        paths.sort();
+        for path in paths {
+            let Ok(canonical) = fs::canonicalize(&path) else {
+                continue;
+            };
+            if seen.insert(canonical) {
+                let interpreter = Interpreter::probe(&path);
+                debug!("Found {}", interpreter);
+                found.push(interpreter);
+            }
+        }
+    }
+    found
+}
+
+/// The interpreter configured with `python`, a path or a name looked up in
+/// PATH like `python3.12`.
+pub fn resolve(python: &str) -> Result<Interpreter, String> {
+    let path = idf_im_lib::expand_tilde(Path::new(python));
+    let path = if path.components().count() > 1 {
+        Some(path).filter(|path| path.is_file())
+    } else {
+        env::var_os("PATH").and_then(|dirs| {
+            env::split_paths(&dirs)
+                .map(|dir| dir.join(&path))
+                .find(|path| path.is_file())
+        })
+    };
+    match path {
+        Some(path) => Ok(Interpreter::probe(&path)),
+        None => Err(t!("python.select.not_found", python = python).to_string()),
+    }
+}
+
+/// Makes `python` the interpreter idf_tools.py and the python environment it
+/// creates use, by putting it first in PATH. On POSIX systems it is linked
+/// as `python3` into a folder of `tools_dir`, which has to stay, since the
+/// python environment refers to the link.
+pub fn use_interpreter(python: &Path, tools_dir: &Path) -> Result<(), String> {
+    let dir = if cfg!(windows) {
+        python.parent().map(Path::to_path_buf).unwrap_or_default()
+    } else {
+        let dir = tools_dir.join(SHIM_FOLDER);
+        fs::create_dir_all(&dir)
+            .map_err(|err| format!("Failed to create {}: {}", dir.display(), err))?;
+        let link = dir.join("python3");
+        if link.symlink_metadata().is_ok() {
+            fs::remove_file(&link)
+                .map_err(|err| format!("Failed to remove {}: {}", link.display(), err))?;
+        }
+        let target = fs::canonicalize(python).unwrap_or_else(|_| python.to_path_buf());
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(&target, &link)
+            .map_err(|err| format!("Failed to link {}: {}", link.display(), err))?;
+        dir
+    };
+    debug!("Running idf_tools.py with {}", python.display());
+    idf_im_lib::add_path_to_path(&dir.to_string_lossy());
+    Ok(())
+}
@@ -0,0 +1,240 @@
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use idf_im_lib::settings::Settings;
+use indicatif::HumanBytes;
+use log::{debug, warn};
+use zip::write::SimpleFileOptions;
+use zip::ZipWriter;
+
+use crate::configuration::{self, sources::SettingsSources, validation};
+use crate::logging::{last_install_logs, split_at_run};
+use crate::network;
+use crate::python;
+
+/// How deep below the installation root the directory summary goes.
+const DIRECTORY_SUMMARY_DEPTH: usize = 2;
+
+fn settings_section(
+    settings: &Result<Settings, Vec<validation::ConfigProblem>>,
+    sources: &SettingsSources,
+) -> String {
+    match settings {
+        Ok(settings) => match configuration::describe_settings(settings, sources, true) {
+            Ok(lines) => lines.join("\n"),
+            Err(err) => format!("Unable to describe settings: {}", err),
+        },
+        Err(problems) => {
+            let mut lines = vec!["Settings are invalid:".to_string()];
+            lines.extend(problems.iter().map(|p| format!("  {}", p)));
+            lines.join("\n")
+        }
+    }
+}
+
+fn prerequisites_section() -> String {
+    match idf_im_lib::system_dependencies::check_prerequisites() {
+        Ok(missing) if missing.is_empty() => "All prerequisites are satisfied".to_string(),
+        Ok(missing) => format!("Missing prerequisites: {}", missing.join(", ")),
+        Err(err) => format!("Prerequisites check failed: {}", err),
+    }
+}
+
+fn python_section(python: Option<&str>) -> String {
+    let mut lines = vec![format!(
+        "Configured interpreter: {}",
+        python.unwrap_or("<unset>")
+    )];
+    lines.extend(
+        idf_im_lib::python_utils::python_sanity_check(python)
+            .into_iter()
+            .map(|result| match result {
+                Ok(output) => format!("OK: {}", output.trim()),
+                Err(err) => format!("FAILED: {}", err.trim()),
+            }),
+    );
+    lines.push("Found interpreters:".to_string());
+    lines.extend(
+        python::discover()
+            .iter()
+            .map(|interpreter| format!("  {}", interpreter)),
+    );
+    lines.join("\n")
+}
+
+fn platform_section(python: Option<&str>) -> String {
+    let platform = match idf_im_lib::idf_tools::get_platform_identification(python) {
+        Ok(platform) => platform,
+        Err(err) => format!("unable to identify: {}", err),
+    };
+    format!(
+        "eim version: {}\nos: {}\narch: {}\ntools platform: {}",
+        env!("CARGO_PKG_VERSION"),
+        std::env::consts::OS,
+        std::env::consts::ARCH,
+        platform
+    )
+}
+
+/// Returns (file count, total size) of `path`, adding a line for every
+/// directory up to `max_depth` levels below the root.
+fn summarize(path: &Path, depth: usize, max_depth: usize, lines: &mut Vec<String>) -> (u64, u64) {
+    let line_index = lines.len();
+    let mut files = 0;
+    let mut size = 0;
+    let mut entries: Vec<_> = match fs::read_dir(path) {
+        Ok(entries) => entries.flatten().collect(),
+        Err(err) => {
+            lines.push(format!(
+                "{}{} ({})",
+                "  ".repeat(depth),
+                path.display(),
+                err
+            ));
+            return (0, 0);
+        }
+    };
+    entries.sort_by_key(|entry| entry.file_name());
+
+    for entry in entries {
+        // symlink_metadata so that links are counted, not followed
+        let Ok(metadata) = entry.path().symlink_metadata() else {
+            continue;
+        };
+        if metadata.is_dir() {
+            let (dir_files, dir_size) = if depth < max_depth {
+                summarize(&entry.path(), depth + 1, max_depth, lines)
+            } else {
+                summarize(&entry.path(), depth + 1, max_depth, &mut vec![])
+            };
+            files += dir_files;
+            size += dir_size;
+        } else {
+            files += 1;
+            size += metadata.len();
+        }
+    }
+
+    let name = if depth == 0 {
+        path.display().to_string()
+    } else {
+        format!(
+            "{}/",
+            path.file_name().unwrap_or_default().to_string_lossy()
+        )
+    };
+    lines.insert(
+        line_index,
+        format!(
+            "{}{}  {} files  {}",
+            "  ".repeat(depth),
+            name,
+            files,
+            HumanBytes(size)
+        ),
+    );
+    (files, size)
+}
+
+fn directory_section(settings: &Result<Settings, Vec<validation::ConfigProblem>>) -> String {
+    let Some(path) = settings.as_ref().ok().and_then(|s| s.path.clone()) else {
+        return "Installation path unknown".to_string();
+    };
+    let path = idf_im_lib::expand_tilde(&path);
+    if !path.exists() {
+        return format!("{} does not exist", path.display());
+    }
+    let mut lines = vec![];
+    summarize(&path, 0, DIRECTORY_SUMMARY_DEPTH, &mut lines);
+    lines.join("\n")
+}
+
+fn add_text(zip: &mut ZipWriter<File>, name: &str, content: &str) -> Result<(), String> {
+    zip.start_file(name, SimpleFileOptions::default())
+        .map_err(|e| format!("Failed to add {} to report: {}", name, e))?;
+    zip.write_all(content.as_bytes())
+        .map_err(|e| format!("Failed to add {} to report: {}", name, e))
+}
+
+/// Adds the logs concatenated into one file, with the credentials of the
+/// URLs in them hidden.
+fn add_logs(zip: &mut ZipWriter<File>, name: &str, logs: &[String]) -> Result<(), String> {
+    let mut content = String::new();
+    for line in logs.iter().flat_map(|log| log.lines()) {
+        content.push_str(&network::hide_credentials(line));
+        content.push('\n');
+    }
+    if content.is_empty() {
+        return Ok(());
+    }
+    add_text(zip, name, &content)
+}
+
+fn read_log(path: &Path) -> String {
+    fs::read_to_string(path).unwrap_or_else(|err| {
+        warn!("Unable to read {}: {}", path.display(), err);
+        String::new()
+    })
+}
+
+/// Collects everything needed to debug a failed installation into one zip
+/// file: the logs of this and the last installation run, the effective
+/// settings (with credentials redacted), prerequisites and python checks,
+/// platform information and a summary of the installation directory.
+pub async fn create_report(
+    run_id: &str,
+    log_file: &Path,
+    sources: &SettingsSources,
+    output: Option<PathBuf>,
+) -> Result<PathBuf, String> {
+    let output = output.unwrap_or_else(|| PathBuf::from(format!("eim-report-{}.zip", run_id)));
+    let settings = validation::load_settings(sources).await;
+
+    let file = File::create(&output)
+        .map_err(|e| format!("Failed to create {}: {}", output.display(), e))?;
+    let mut zip = ZipWriter::new(file);
+
+    debug!("Collecting settings");
+    add_text(
+        &mut zip,
+        "settings.txt",
+        &settings_section(&settings, sources),
+    )?;
+    debug!("Checking prerequisites");
+    add_text(&mut zip, "prerequisites.txt", &prerequisites_section())?;
+    debug!("Running python sanity check");
+    let python = configuration::eim_setting(sources, "python").unwrap_or_default();
+    add_text(&mut zip, "python.txt", &python_section(python.as_deref()))?;
+    add_text(
+        &mut zip,
+        "platform.txt",
+        &platform_section(python.as_deref()),
+    )?;
+    debug!("Summarizing installation directory");
+    add_text(&mut zip, "install_dir.txt", &directory_section(&settings))?;
+
+    // this run is appended to the log file, after the failed install and
+    // the runs since then, unless another install started in between
+    let current_log = read_log(log_file);
+    let (earlier_runs, report_run) = split_at_run(&current_log, run_id);
+    let install_logs: Vec<String> = last_install_logs(log_file)
+        .iter()
+        .map(|path| {
+            if path == log_file {
+                earlier_runs.to_string()
+            } else {
+                read_log(path)
+            }
+        })
+        .collect();
+    if install_logs.is_empty() {
+        warn!("No log of an installation found");
+    }
+    add_logs(&mut zip, "logs/install_run.log", &install_logs)?;
+    add_logs(&mut zip, "logs/report_run.log", &[report_run.to_string()])?;
+
+    zip.finish()
+        .map_err(|e| format!("Failed to write {}: {}", output.display(), e))?;
+    Ok(output)
+}
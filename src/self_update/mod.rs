@@ -0,0 +1,339 @@
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use log::{debug, info, warn};
+use minisign_verify::Signature;
+use rust_i18n::t;
+use serde_json::Value;
+
+use crate::network;
+use crate::signatures::TrustedKeys;
+use crate::versions::release_number;
+
+/// Latest release of eim, in the format of the GitHub releases API.
+pub const DEFAULT_RELEASE_URL: &str =
+    "https://api.github.com/repos/espressif/idf-im-cli/releases/latest";
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Where the new binary is prepared, next to the running one so that it can
+/// be renamed into place.
+const STAGING_DIR_NAME: &str = ".eim-self-update";
+
+pub struct SelfUpdateOptions {
+    pub release_url: String,
+    /// Only report whether a newer version is available.
+    pub check_only: bool,
+    /// With minisign keys the archive has to be signed by one of them.
+    pub trusted_keys: TrustedKeys,
+}
+
+/// The archive of a release for this platform.
+struct ReleaseAsset {
+    version: String,
+    name: String,
+    url: String,
+    sha256: String,
+    /// Of the minisign signature, published as `<name>.minisig`.
+    signature_url: Option<String>,
+}
+
+/// The suffix of the release archives built for this platform, as named in
+/// the release workflow.
+fn package_name() -> Option<&'static str> {
+    match (std::env::consts::OS, std::env::consts::ARCH) {
+        ("linux", "x86_64") => Some("linux-x64"),
+        ("linux", "aarch64") => Some("linux-arm64"),
+        ("windows", "x86_64") => Some("windows-x64"),
+        ("macos", "x86_64") => Some("macos-x64"),
+        ("macos", "aarch64") => Some("macos-aarch64"),
+        _ => None,
+    }
+}
+
+async fn get(client: &reqwest::Client, url: &str) -> Result<reqwest::Response, String> {
+    client
+        .get(url)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|err| format!("Failed to download {}: {}", url, err))
+}
+
+/// The checksum of `asset`, from its `digest` or else from a `<name>.sha256`
+/// file published next to it.
+async fn asset_checksum(
+    client: &reqwest::Client,
+    assets: &[Value],
+    asset: &Value,
+) -> Result<Option<String>, String> {
+    if let Some(digest) = asset["digest"]
+        .as_str()
+        .and_then(|digest| digest.strip_prefix("sha256:"))
+    {
+        return Ok(Some(digest.to_string()));
+    }
+    let checksum_name = format!("{}.sha256", asset["name"].as_str().unwrap_or_default());
+    let Some(url) = assets
+        .iter()
+        .find(|other| other["name"] == checksum_name.as_str())
+        .and_then(|other| other["browser_download_url"].as_str())
+    else {
+        return Ok(None);
+    };
+    let content = get(client, url)
+        .await?
+        .text()
+        .await
+        .map_err(|err| format!("Failed to download {}: {}", url, err))?;
+    // `sha256sum` format, the checksum followed by the file name
+    Ok(content.split_whitespace().next().map(str::to_lowercase))
+}
+
+async fn fetch_release(
+    client: &reqwest::Client,
+    release_url: &str,
+    package: &str,
+) -> Result<ReleaseAsset, String> {
+    let content = get(client, release_url)
+        .await?
+        .text()
+        .await
+        .map_err(|err| format!("Failed to download {}: {}", release_url, err))?;
+    let release: Value = serde_json::from_str(&content)
+        .map_err(|err| format!("Failed to parse {}: {}", release_url, err))?;
+    let version = release["tag_name"].as_str().unwrap_or_default().to_string();
+    let assets = release["assets"].as_array().cloned().unwrap_or_default();
+
+    let name = format!("eim-{}-{}.zip", version, package);
+    let Some(asset) = assets.iter().find(|asset| asset["name"] == name.as_str()) else {
+        return Err(t!(
+            "self_update.no_asset",
+            version = version,
+            platform = package
+        )
+        .to_string());
+    };
+    let Some(sha256) = asset_checksum(client, &assets, asset).await? else {
+        return Err(t!("self_update.no_checksum", file = name).to_string());
+    };
+    let signature_name = format!("{}.minisig", name);
+    let signature_url = assets
+        .iter()
+        .find(|other| other["name"] == signature_name.as_str())
+        .and_then(|other| other["browser_download_url"].as_str())
+        .map(str::to_string);
+    Ok(ReleaseAsset {
+        version,
+        url: asset["browser_download_url"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string(),
+        name,
+        sha256,
+        signature_url,
+    })
+}
+
+/// Verifies the signature of the downloaded `archive` when minisign keys are
+/// trusted. The checksum comes from the same index as the archive, the
+/// signature proves who published it.
+async fn verify_signature(
+    client: &reqwest::Client,
+    asset: &ReleaseAsset,
+    archive: &Path,
+    trusted_keys: &TrustedKeys,
+) -> Result<(), String> {
+    if !trusted_keys.has_minisign_keys() {
+        return Ok(());
+    }
+    let Some(url) = &asset.signature_url else {
+        return Err(t!("self_update.no_signature", file = &asset.name).to_string());
+    };
+    let signature = get(client, url)
+        .await?
+        .text()
+        .await
+        .map_err(|err| err.to_string())
+        .and_then(|content| Signature::decode(&content).map_err(|err| err.to_string()))
+        .map_err(|err| t!("signatures.missing", url = url, error = err).to_string())?;
+    trusted_keys.verify_file(archive, &signature)?;
+    info!("{}", t!("signatures.archive_verified", file = &asset.name));
+    Ok(())
+}
+
+/// Extracts the eim binary from the release archive.
+fn extract_binary(archive: &Path, destination: &Path) -> Result<(), String> {
+    let binary_name = format!("eim{}", std::env::consts::EXE_SUFFIX);
+    let file = File::open(archive)
+        .map_err(|err| format!("Failed to open {}: {}", archive.display(), err))?;
+    let mut zip = zip::ZipArchive::new(file)
+        .map_err(|err| format!("Failed to read {}: {}", archive.display(), err))?;
+    let mut entry = zip.by_name(&binary_name).map_err(|err| {
+        format!(
+            "{} not found in {}: {}",
+            binary_name,
+            archive.display(),
+            err
+        )
+    })?;
+    let mut output = File::create(destination)
+        .map_err(|err| format!("Failed to create {}: {}", destination.display(), err))?;
+    std::io::copy(&mut entry, &mut output)
+        .map_err(|err| format!("Failed to write {}: {}", destination.display(), err))?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(destination, fs::Permissions::from_mode(0o755)).map_err(|err| {
+            format!(
+                "Failed to make {} executable: {}",
+                destination.display(),
+                err
+            )
+        })?;
+    }
+    Ok(())
+}
+
+/// Runs the new binary once, so that a broken download never replaces a
+/// working eim.
+fn check_binary(binary: &Path, version: &str) -> Result<(), String> {
+    let output = Command::new(binary)
+        .arg("--version")
+        .output()
+        .map_err(|err| t!("self_update.broken_binary", error = err).to_string())?;
+    let reported = String::from_utf8_lossy(&output.stdout);
+    debug!("New binary reports {}", reported.trim());
+    if !output.status.success() || !reported.contains(version.trim_start_matches('v')) {
+        return Err(t!("self_update.broken_binary", error = reported.trim()).to_string());
+    }
+    Ok(())
+}
+
+/// Leftover of the previous update on Windows, see `replace_binary`.
+fn old_binary_path(exe: &Path) -> PathBuf {
+    exe.with_extension("old")
+}
+
+/// Renames `new` over `exe`. The running binary can't be replaced on
+/// Windows, but it can be renamed out of the way and is removed by the next
+/// update.
+fn replace_binary(new: &Path, exe: &Path) -> Result<(), String> {
+    let replace_error =
+        |err: std::io::Error| format!("Failed to replace {}: {}", exe.display(), err);
+    if cfg!(windows) {
+        let old = old_binary_path(exe);
+        fs::rename(exe, &old).map_err(replace_error)?;
+        if let Err(err) = fs::rename(new, exe) {
+            let _ = fs::rename(&old, exe);
+            return Err(replace_error(err));
+        }
+        Ok(())
+    } else {
+        fs::rename(new, exe).map_err(replace_error)
+    }
+}
+
+async fn download_and_replace(
+    client: &reqwest::Client,
+    asset: &ReleaseAsset,
+    exe: &Path,
+    staging_dir: &Path,
+    trusted_keys: &TrustedKeys,
+) -> Result<(), String> {
+    info!("{}", t!("self_update.downloading", file = &asset.name));
+    let content = get(client, &asset.url)
+        .await?
+        .bytes()
+        .await
+        .map_err(|err| format!("Failed to download {}: {}", asset.url, err))?;
+    let archive = staging_dir.join(&asset.name);
+    fs::write(&archive, &content)
+        .map_err(|err| format!("Failed to write {}: {}", archive.display(), err))?;
+
+    match idf_im_lib::verify_file_checksum(&asset.sha256, archive.to_str().unwrap()) {
+        Ok(true) => debug!("Checksum of {} verified", asset.name),
+        _ => return Err(t!("self_update.checksum_mismatch", file = &asset.name).to_string()),
+    }
+    verify_signature(client, asset, &archive, trusted_keys).await?;
+
+    let new_binary = staging_dir.join(exe.file_name().unwrap_or_default());
+    extract_binary(&archive, &new_binary)?;
+    check_binary(&new_binary, &asset.version)?;
+    replace_binary(&new_binary, exe)
+}
+
+/// Replaces the running eim with the latest release for this platform, if
+/// it is newer.
+pub async fn self_update(options: &SelfUpdateOptions) -> Result<(), String> {
+    let Some(package) = package_name() else {
+        return Err(t!(
+            "self_update.unsupported_platform",
+            os = std::env::consts::OS,
+            arch = std::env::consts::ARCH
+        )
+        .to_string());
+    };
+    let exe = std::env::current_exe()
+        .and_then(|exe| exe.canonicalize())
+        .map_err(|err| format!("Failed to locate the eim binary: {}", err))?;
+    if old_binary_path(&exe).exists() {
+        let _ = fs::remove_file(old_binary_path(&exe));
+    }
+
+    info!("{}", t!("self_update.checking", url = &options.release_url));
+    let client = network::http_client()?;
+    let asset = fetch_release(&client, &options.release_url, package).await?;
+
+    let current = release_number(&format!("v{}", VERSION));
+    match release_number(&asset.version) {
+        Some(latest) if Some(latest) > current => {}
+        Some(_) => {
+            info!("{}", t!("self_update.up_to_date", version = VERSION));
+            return Ok(());
+        }
+        None => return Err(t!("self_update.invalid_version", version = &asset.version).to_string()),
+    }
+    info!(
+        "{}",
+        t!(
+            "self_update.available",
+            version = &asset.version,
+            current = VERSION
+        )
+    );
+    if options.check_only {
+        return Ok(());
+    }
+
+    let staging_dir = exe
+        .parent()
+        .unwrap_or(Path::new("."))
+        .join(STAGING_DIR_NAME);
+    fs::create_dir_all(&staging_dir).map_err(|err| {
+        t!(
+            "self_update.not_writable",
+            path = exe.display(),
+            error = err
+        )
+        .to_string()
+    })?;
+    let result =
+        download_and_replace(&client, &asset, &exe, &staging_dir, &options.trusted_keys).await;
+    if let Err(err) = fs::remove_dir_all(&staging_dir) {
+        warn!("Failed to remove {}: {}", staging_dir.display(), err);
+    }
+    result?;
+
+    info!(
+        "{}",
+        t!(
+            "self_update.finished",
+            version = &asset.version,
+            path = exe.display()
+        )
+    );
+    Ok(())
+}
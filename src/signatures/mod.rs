@@ -0,0 +1,260 @@
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::mpsc;
+
+use git2::Repository;
+use log::{debug, info, warn};
+use minisign_verify::{PublicKey, Signature, StreamVerifier};
+use rust_i18n::t;
+
+use crate::network;
+use crate::wizard::helpers::generic_confirm;
+
+const PGP_PUBLIC_KEY_HEADER: &str = "-----BEGIN PGP PUBLIC KEY BLOCK-----";
+const PGP_SIGNATURE_HEADER: &str = "-----BEGIN PGP SIGNATURE-----";
+/// Detached minisign signatures are published next to the archives.
+const MINISIGN_SUFFIX: &str = ".minisig";
+/// Archives are verified in chunks of this size, toolchains take hundreds of
+/// megabytes.
+const READ_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Keys downloads are verified with. Tool archives are verified when
+/// minisign keys are configured, the ESP-IDF tag when GPG keys are.
+#[derive(Debug, Default)]
+pub struct TrustedKeys {
+    minisign: Vec<PublicKey>,
+    /// Armored GPG public keys, imported into a temporary keyring.
+    gpg: Vec<PathBuf>,
+}
+
+impl TrustedKeys {
+    /// Reads the key files, telling GPG keys from minisign ones by their
+    /// content.
+    pub fn load(files: &[PathBuf]) -> Result<Self, String> {
+        let mut keys = TrustedKeys::default();
+        for file in files {
+            let content = fs::read_to_string(file)
+                .map_err(|err| format!("Failed to read {}: {}", file.display(), err))?;
+            if content.contains(PGP_PUBLIC_KEY_HEADER) {
+                keys.gpg.push(file.clone());
+                continue;
+            }
+            // the .pub file of minisign, or only its base64 line
+            let key = PublicKey::decode(&content)
+                .or_else(|_| PublicKey::from_base64(content.trim()))
+                .map_err(|err| t!("signatures.invalid_key", path = file.display(), error = err))?;
+            keys.minisign.push(key);
+        }
+        debug!(
+            "Trusted keys: {} minisign, {} GPG",
+            keys.minisign.len(),
+            keys.gpg.len()
+        );
+        Ok(keys)
+    }
+
+    /// Whether minisign keys are trusted, archives are only verified then.
+    pub fn has_minisign_keys(&self) -> bool {
+        !self.minisign.is_empty()
+    }
+
+    /// Verifies the downloaded `archive` against the signature published at
+    /// `<url>.minisig`.
+    pub async fn verify_archive(
+        &self,
+        url: &str,
+        archive: &Path,
+        non_interactive: bool,
+    ) -> Result<(), String> {
+        if !self.has_minisign_keys() {
+            return Ok(());
+        }
+        let result = self.check_archive(url, archive).await;
+        if result.is_ok() {
+            info!(
+                "{}",
+                t!("signatures.archive_verified", file = archive.display())
+            );
+        }
+        enforce(result, non_interactive)
+    }
+
+    async fn check_archive(&self, url: &str, archive: &Path) -> Result<(), String> {
+        let signature_url = format!("{}{}", url, MINISIGN_SUFFIX);
+        let signature_dir = archive.parent().unwrap_or(Path::new("."));
+        // the progress is not shown, the receiver only keeps the channel open
+        let (progress_tx, _progress_rx) = mpsc::channel();
+        idf_im_lib::download_file(
+            &network::authenticated_url(&signature_url),
+            signature_dir.to_str().unwrap(),
+            progress_tx,
+        )
+        .await
+        .map_err(|err| {
+            let error = network::hide_credentials(&err.to_string());
+            t!("signatures.missing", url = &signature_url, error = error)
+        })?;
+        let signature_path = signature_dir.join(format!(
+            "{}{}",
+            url.rsplit('/').next().unwrap(),
+            MINISIGN_SUFFIX
+        ));
+        let signature = fs::read_to_string(&signature_path)
+            .map_err(|err| err.to_string())
+            .and_then(|content| Signature::decode(&content).map_err(|err| err.to_string()))
+            .map_err(|err| t!("signatures.missing", url = &signature_url, error = err))?;
+        self.verify_file(archive, &signature)
+    }
+
+    /// Checks that `signature` of `file` was made by one of the minisign
+    /// keys. The file is read in chunks, only legacy signatures (`minisign
+    /// -l`), which can't be verified as a stream, need it in memory at once.
+    pub fn verify_file(&self, file: &Path, signature: &Signature) -> Result<(), String> {
+        let read_error =
+            |err: std::io::Error| format!("Failed to read {}: {}", file.display(), err);
+        let mut verifiers: Vec<StreamVerifier> = self
+            .minisign
+            .iter()
+            .filter_map(|key| key.verify_stream(signature).ok())
+            .collect();
+        let verified = if verifiers.is_empty() {
+            let content = fs::read(file).map_err(read_error)?;
+            self.minisign
+                .iter()
+                .any(|key| key.verify(&content, signature, false).is_ok())
+        } else {
+            let mut reader = File::open(file).map_err(read_error)?;
+            let mut chunk = vec![0; READ_CHUNK_SIZE];
+            loop {
+                let read = reader.read(&mut chunk).map_err(read_error)?;
+                if read == 0 {
+                    break;
+                }
+                for verifier in &mut verifiers {
+                    verifier.update(&chunk[..read]);
+                }
+            }
+            verifiers
+                .iter_mut()
+                .any(|verifier| verifier.finalize().is_ok())
+        };
+        if verified {
+            Ok(())
+        } else {
+            Err(t!("signatures.archive_invalid", file = file.display()).to_string())
+        }
+    }
+
+    /// Verifies the GPG signature of the annotated tag `version` in the
+    /// repository at `repo_path`, like `git verify-tag`. Branches have no
+    /// signature and fail the verification.
+    pub fn verify_tag(
+        &self,
+        repo_path: &Path,
+        version: &str,
+        non_interactive: bool,
+    ) -> Result<(), String> {
+        if self.gpg.is_empty() {
+            return Ok(());
+        }
+        let result = self.check_tag(repo_path, version);
+        if result.is_ok() {
+            info!("{}", t!("signatures.tag_verified", version = version));
+        }
+        enforce(result, non_interactive)
+    }
+
+    fn check_tag(&self, repo_path: &Path, version: &str) -> Result<(), String> {
+        let unsigned = || t!("signatures.tag_unsigned", version = version).to_string();
+        let repo = Repository::open(repo_path).map_err(|err| err.to_string())?;
+        let tag = repo
+            .find_reference(&format!("refs/tags/{}", version))
+            .and_then(|reference| reference.peel_to_tag())
+            .map_err(|_| unsigned())?;
+        let odb = repo.odb().map_err(|err| err.to_string())?;
+        let object = odb.read(tag.id()).map_err(|err| err.to_string())?;
+        let content = String::from_utf8_lossy(object.data());
+        let Some(start) = content.find(PGP_SIGNATURE_HEADER) else {
+            return Err(unsigned());
+        };
+        let (signed, signature) = content.split_at(start);
+
+        let keyring = std::env::temp_dir().join(format!("eim-gpg-{}", std::process::id()));
+        let result = self.gpg_verify(&keyring, signed, signature);
+        let _ = fs::remove_dir_all(&keyring);
+        result
+            .map_err(|err| t!("signatures.tag_invalid", version = version, error = err).to_string())
+    }
+
+    /// Verifies with a keyring holding only the trusted keys, so a good
+    /// signature can only come from one of them.
+    fn gpg_verify(&self, keyring: &Path, signed: &str, signature: &str) -> Result<(), String> {
+        fs::create_dir_all(keyring).map_err(|err| err.to_string())?;
+        #[cfg(unix)]
+        {
+            // gpg warns about a homedir others can read
+            use std::os::unix::fs::PermissionsExt;
+            fs::set_permissions(keyring, fs::Permissions::from_mode(0o700))
+                .map_err(|err| err.to_string())?;
+        }
+        let data_path = keyring.join("tag");
+        let signature_path = keyring.join("tag.asc");
+        fs::write(&data_path, signed).map_err(|err| err.to_string())?;
+        fs::write(&signature_path, signature).map_err(|err| err.to_string())?;
+        let gpg = |args: &[&std::ffi::OsStr]| {
+            Command::new("gpg")
+                .arg("--batch")
+                .arg("--homedir")
+                .arg(keyring)
+                .args(args)
+                .output()
+                .map_err(|err| t!("signatures.gpg_missing", error = err).to_string())
+        };
+
+        for key in &self.gpg {
+            let output = gpg(&["--import".as_ref(), key.as_os_str()])?;
+            if !output.status.success() {
+                return Err(gpg_error(&output));
+            }
+        }
+        let output = gpg(&[
+            "--status-fd".as_ref(),
+            "1".as_ref(),
+            "--verify".as_ref(),
+            signature_path.as_os_str(),
+            data_path.as_os_str(),
+        ])?;
+        let status = String::from_utf8_lossy(&output.stdout);
+        debug!("gpg status: {}", status.trim());
+        if output.status.success() && status.contains("[GNUPG:] VALIDSIG") {
+            Ok(())
+        } else {
+            Err(gpg_error(&output))
+        }
+    }
+}
+
+/// The last line of the gpg output, which says why it failed.
+fn gpg_error(output: &std::process::Output) -> String {
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    debug!("gpg: {}", stderr.trim());
+    stderr.trim().lines().last().unwrap_or_default().to_string()
+}
+
+/// A failed verification stops non-interactive runs, interactive ones ask
+/// whether to continue anyway.
+fn enforce(result: Result<(), String>, non_interactive: bool) -> Result<(), String> {
+    let Err(err) = result else {
+        return Ok(());
+    };
+    if !non_interactive {
+        warn!("{}", err);
+        if generic_confirm("signatures.continue_prompt").unwrap_or(false) {
+            warn!("{}", t!("signatures.ignored"));
+            return Ok(());
+        }
+    }
+    Err(err)
+}
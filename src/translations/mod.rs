@@ -0,0 +1,137 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use config::{File, Map, Source, Value, ValueKind};
+use log::{debug, warn};
+use rust_i18n::{t, Backend};
+
+use crate::configuration::sources::env_var_name;
+
+const LOCALE_FILE_EXTENSIONS: [&str; 4] = ["yml", "yaml", "json", "toml"];
+
+/// Directory with additional locale files, `EIM_LOCALE_DIR` or
+/// `<config dir>/eim/locales`.
+pub fn user_locale_dir() -> Option<PathBuf> {
+    std::env::var(env_var_name("locale_dir"))
+        .ok()
+        .map(PathBuf::from)
+        .or_else(|| dirs::config_dir().map(|dir| dir.join("eim").join("locales")))
+}
+
+/// Translations read at runtime from the user locale directory. The files use
+/// the same layout as `locales/app.yml`, so new languages can be added (or
+/// the built-in ones corrected) without recompiling.
+#[derive(Default)]
+pub struct UserTranslations {
+    // locale -> key -> message
+    translations: HashMap<String, HashMap<String, String>>,
+}
+
+impl UserTranslations {
+    pub fn load() -> Self {
+        let mut user_translations = UserTranslations::default();
+        let Some(dir) = user_locale_dir() else {
+            return user_translations;
+        };
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            return user_translations;
+        };
+        let mut files: Vec<PathBuf> = entries
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.extension()
+                    .and_then(|ext| ext.to_str())
+                    .is_some_and(|ext| LOCALE_FILE_EXTENSIONS.contains(&ext))
+            })
+            .collect();
+        files.sort();
+        for file in files {
+            if let Err(err) = user_translations.add_file(&file) {
+                warn!("Ignoring locale file {}: {}", file.display(), err);
+            }
+        }
+        user_translations
+    }
+
+    fn add_file(&mut self, path: &Path) -> Result<(), String> {
+        let table = File::from(path.to_path_buf())
+            .collect()
+            .map_err(|err| err.to_string())?;
+        debug!("Loading translations from {}", path.display());
+        self.add_table("", table);
+        Ok(())
+    }
+
+    /// Accepts both `wizard.idf.success: {en: ...}` and nested keys; a table
+    /// holding only strings is the set of translations of one key.
+    fn add_table(&mut self, prefix: &str, table: Map<String, Value>) {
+        let only_strings = table
+            .values()
+            .all(|value| matches!(value.kind, ValueKind::String(_)));
+        for (name, value) in table {
+            match value.kind {
+                ValueKind::String(message) if only_strings && !prefix.is_empty() => {
+                    self.translations
+                        .entry(name)
+                        .or_default()
+                        .insert(prefix.to_string(), message);
+                }
+                ValueKind::Table(nested) => {
+                    let key = if prefix.is_empty() {
+                        name
+                    } else {
+                        format!("{}.{}", prefix, name)
+                    };
+                    self.add_table(&key, nested);
+                }
+                // e.g. `_version: 2`
+                _ => {}
+            }
+        }
+    }
+}
+
+impl Backend for UserTranslations {
+    fn available_locales(&self) -> Vec<&str> {
+        self.translations
+            .keys()
+            .map(|locale| locale.as_str())
+            .collect()
+    }
+
+    fn translate(&self, locale: &str, key: &str) -> Option<&str> {
+        self.translations
+            .get(locale)
+            .and_then(|messages| messages.get(key))
+            .map(|message| message.as_str())
+    }
+}
+
+/// CLDR plural category of `count` in the current locale. Only the
+/// categories used by the bundled languages are distinguished.
+fn plural_category(locale: &str, count: usize) -> &'static str {
+    match (locale, count) {
+        ("cn" | "ja", _) => "other",
+        ("cs", 1) => "one",
+        ("cs", 2..=4) => "few",
+        (_, 1) => "one",
+        _ => "other",
+    }
+}
+
+/// Translates `<key>.one`, `<key>.few` or `<key>.other` depending on `count`,
+/// replacing `%{count}`. Languages without a `few` form use `other`.
+pub fn plural(key: &str, count: usize) -> String {
+    let locale = rust_i18n::locale().to_string();
+    // t! returns the key of a message missing in all languages
+    let translate = |category: &str| {
+        let key = format!("{}.{}", key, category);
+        let message = t!(&key, count = count).to_string();
+        (message != key).then_some(message)
+    };
+    let category = plural_category(&locale, count);
+    translate(category)
+        .or_else(|| translate("other"))
+        .unwrap_or_else(|| format!("{}.{}", key, category))
+}
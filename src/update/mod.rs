@@ -0,0 +1,524 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use git2::build::{CloneLocal, RepoBuilder};
+use git2::{AutotagOption, Direction, Oid, Repository, ResetType};
+use idf_im_lib::idf_tools::{self, Tool, ToolsFile};
+use idf_im_lib::settings::Settings;
+use log::{info, warn};
+use rust_i18n::t;
+
+use crate::configuration::mirrors::{Mirrors, IDF_REPOSITORY_URL};
+use crate::git::{
+    self, checkout_options, fetch_options, reference_dir, update_submodules, CloneProgress,
+};
+use crate::ide_json;
+use crate::interrupt;
+use crate::lock::DirLock;
+use crate::logging::Step;
+use crate::network;
+use crate::python;
+use crate::signatures::TrustedKeys;
+use crate::versions::newest_patch;
+use crate::wizard::install_state::InstallState;
+use crate::wizard::transaction::VersionTransaction;
+use crate::wizard::{self, DEFAULT_TOOLS_DOWNLOAD_FOLDER, DEFAULT_TOOLS_INSTALL_FOLDER};
+
+/// Options of `eim update`.
+#[derive(Debug, Default)]
+pub struct UpdateOptions {
+    /// Version to update to, instead of the newest patch release.
+    pub to: Option<String>,
+    /// Install the new version next to the old one instead of replacing it.
+    pub keep_old: bool,
+    pub wait_for_lock: bool,
+    pub trusted_keys: TrustedKeys,
+    pub mirrors: Mirrors,
+    /// Python interpreter to set up the python environment with.
+    pub python: Option<String>,
+    /// Number of commits of history to clone new submodules with, all of it
+    /// if `None`.
+    pub clone_depth: Option<u32>,
+    /// Skip the submodules the selected targets don't need.
+    pub skip_unused_submodules: bool,
+}
+
+/// What an installed version is updated to.
+enum Target {
+    /// A release, checked out in `<path>/<tag>`.
+    Tag(String),
+    /// A branch like `master`, updated to its latest commit in place.
+    Branch(String),
+}
+
+impl Target {
+    fn name(&self) -> &str {
+        match self {
+            Target::Tag(name) | Target::Branch(name) => name,
+        }
+    }
+}
+
+fn git_error(err: git2::Error) -> String {
+    if git::is_interrupted(&err) {
+        return interrupt::stopped();
+    }
+    t!("update.git_failed", error = err.message()).to_string()
+}
+
+/// Names of the refs (`refs/tags/v5.3.2`, `refs/heads/master`) of origin.
+fn remote_refs(repo: &Repository) -> Result<Vec<String>, git2::Error> {
+    let mut remote = repo.find_remote("origin")?;
+    let url = remote.url().unwrap_or_default().to_string();
+    remote.connect_auth(
+        Direction::Fetch,
+        Some(network::git_callbacks()),
+        Some(network::git_proxy_options(&url)),
+    )?;
+    let refs = remote
+        .list()?
+        .iter()
+        .map(|head| head.name().to_string())
+        .collect();
+    remote.disconnect()?;
+    Ok(refs)
+}
+
+/// `None` when the version is already the newest release.
+fn select_target(
+    repo: &Repository,
+    version: &str,
+    to: Option<&str>,
+) -> Result<Option<Target>, String> {
+    let refs = remote_refs(repo).map_err(git_error)?;
+    let has_ref = |name: &str| refs.iter().any(|r| r == name);
+
+    if let Some(to) = to {
+        return if has_ref(&format!("refs/tags/{}", to)) {
+            Ok(Some(Target::Tag(to.to_string())))
+        } else if has_ref(&format!("refs/heads/{}", to)) {
+            Ok(Some(Target::Branch(to.to_string())))
+        } else {
+            Err(t!("update.unknown_version", version = to).to_string())
+        };
+    }
+
+    let head = repo.head().map_err(git_error)?;
+    if head.is_branch() {
+        let branch = head.shorthand().unwrap_or(version).to_string();
+        return Ok(Some(Target::Branch(branch)));
+    }
+    let tags = refs.iter().filter_map(|r| r.strip_prefix("refs/tags/"));
+    match newest_patch(version, tags) {
+        Some(newest) if newest != version => Ok(Some(Target::Tag(newest))),
+        Some(_) => Ok(None),
+        None => Err(t!("update.no_release", version = version).to_string()),
+    }
+}
+
+/// Fetches `target`, returning the commit to check out.
+fn fetch(repo: &Repository, target: &Target, progress: &CloneProgress) -> Result<Oid, git2::Error> {
+    let _step = Step::start("fetch_idf");
+    let (refspec, reference) = match target {
+        Target::Tag(tag) => (
+            format!("+refs/tags/{0}:refs/tags/{0}", tag),
+            format!("refs/tags/{}", tag),
+        ),
+        Target::Branch(branch) => (
+            format!("+refs/heads/{0}:refs/remotes/origin/{0}", branch),
+            format!("refs/remotes/origin/{}", branch),
+        ),
+    };
+    // with tag following, libgit2 keeps a local tag the refspec should replace
+    progress.start_repository("esp-idf");
+    let mut remote = repo.find_remote("origin")?;
+    let url = remote.url().unwrap_or_default().to_string();
+    let mut options = fetch_options(progress, &url);
+    options.download_tags(AutotagOption::None);
+    remote.fetch(&[&refspec], Some(&mut options), None)?;
+    Ok(repo.find_reference(&reference)?.peel_to_commit()?.id())
+}
+
+/// Checks out `commit`, discarding local changes.
+fn checkout(
+    repo: &Repository,
+    target: &Target,
+    commit: Oid,
+    progress: &CloneProgress,
+) -> Result<(), git2::Error> {
+    let commit = repo.find_commit(commit)?;
+    if let Target::Tag(_) = target {
+        repo.set_head_detached(commit.id())?;
+    }
+    repo.reset(
+        commit.as_object(),
+        ResetType::Hard,
+        Some(&mut checkout_options(progress)),
+    )
+}
+
+/// Clones the local `from` repository, keeping its origin.
+fn clone_local(from: &Path, to: &Path) -> Result<(), git2::Error> {
+    let origin = Repository::open(from)?
+        .find_remote("origin")?
+        .url()
+        .map(str::to_string);
+    let repo = RepoBuilder::new()
+        .clone_local(CloneLocal::Local)
+        .clone(from.to_str().unwrap(), to)?;
+    if let Some(url) = origin {
+        repo.remote_set_url("origin", &url)?;
+    }
+    Ok(())
+}
+
+fn link_or_copy(from: &Path, to: &Path) -> Result<(), String> {
+    if fs::hard_link(from, to).is_err() {
+        fs::copy(from, to).map_err(|err| format!("Failed to copy {}: {}", from.display(), err))?;
+    }
+    Ok(())
+}
+
+/// Makes the downloaded archives of the old version available to the new
+/// one, so that only the tools which changed are downloaded.
+fn link_archives(from: &Path, to: &Path) -> Result<(), String> {
+    idf_im_lib::ensure_path(to.to_str().unwrap()).map_err(|err| err.to_string())?;
+    let Ok(entries) = fs::read_dir(from) else {
+        return Ok(());
+    };
+    for entry in entries.flatten().filter(|e| e.path().is_file()) {
+        link_or_copy(&entry.path(), &to.join(entry.file_name()))?;
+    }
+    Ok(())
+}
+
+/// Makes the installed tools of the old version available to the new one,
+/// so that only the tools which changed are installed. The extracted tools
+/// are hard linked, idf_tools.py puts other versions of a tool next to them
+/// and never writes into them. The files directly in the folder (like
+/// `idf-env.json`) are rewritten in place, so they are copied, and the
+/// python environments are left out, they are created again.
+fn link_tools(from: &Path, to: &Path) -> Result<(), String> {
+    fn link_tree(from: &Path, to: &Path) -> Result<(), String> {
+        fs::create_dir_all(to)
+            .map_err(|err| format!("Failed to create {}: {}", to.display(), err))?;
+        let entries = fs::read_dir(from)
+            .map_err(|err| format!("Failed to read {}: {}", from.display(), err))?;
+        for entry in entries.flatten() {
+            let (path, target) = (entry.path(), to.join(entry.file_name()));
+            let file_type = entry.file_type().map_err(|err| err.to_string())?;
+            if file_type.is_symlink() {
+                let link = fs::read_link(&path).map_err(|err| err.to_string())?;
+                #[cfg(unix)]
+                std::os::unix::fs::symlink(&link, &target)
+                    .map_err(|err| format!("Failed to link {}: {}", target.display(), err))?;
+                #[cfg(not(unix))]
+                let _ = link;
+            } else if file_type.is_dir() {
+                link_tree(&path, &target)?;
+            } else {
+                link_or_copy(&path, &target)?;
+            }
+        }
+        Ok(())
+    }
+
+    idf_im_lib::ensure_path(to.to_str().unwrap()).map_err(|err| err.to_string())?;
+    let Ok(entries) = fs::read_dir(from) else {
+        return Ok(());
+    };
+    for entry in entries.flatten() {
+        let (path, target) = (entry.path(), to.join(entry.file_name()));
+        if entry.file_name() == python::ENV_FOLDER {
+            continue;
+        }
+        if path.is_dir() {
+            link_tree(&path, &target)?;
+        } else {
+            fs::copy(&path, &target)
+                .map_err(|err| format!("Failed to copy {}: {}", path.display(), err))?;
+        }
+    }
+    Ok(())
+}
+
+fn read_tools_file(idf_path: &Path, config: &Settings) -> Result<ToolsFile, String> {
+    let tools_json_file = idf_path.join(
+        config
+            .tools_json_file
+            .as_deref()
+            .unwrap_or(wizard::DEFAULT_TOOLS_JSON_LOCATION),
+    );
+    idf_tools::read_and_parse_tools_file(tools_json_file.to_str().unwrap())
+        .map_err(|err| t!("wizard.tools_json.unparsable", e = err).to_string())
+}
+
+/// The recommended versions of a tool with the checksums of their archives.
+fn recommended_versions(tool: &Tool) -> Vec<(String, Vec<String>)> {
+    tool.versions
+        .iter()
+        .filter(|version| version.status == "recommended")
+        .map(|version| {
+            let mut checksums: Vec<String> = version
+                .downloads
+                .values()
+                .map(|download| download.sha256.clone())
+                .collect();
+            checksums.sort();
+            (version.name.clone(), checksums)
+        })
+        .collect()
+}
+
+/// The tools of `new` which are not in `old` or have another recommended
+/// version.
+fn changed_tools(old: &ToolsFile, new: &ToolsFile) -> ToolsFile {
+    let mut changed = new.clone();
+    changed.tools.retain(|tool| {
+        old.tools
+            .iter()
+            .find(|old_tool| old_tool.name == tool.name)
+            .is_none_or(|old_tool| recommended_versions(old_tool) != recommended_versions(tool))
+    });
+    changed
+}
+
+/// The directory `version` is installed in, as listed in esp_ide.json or in
+/// the installation path.
+fn find_version_dir(config: &Settings, version: &str) -> Result<PathBuf, String> {
+    let listed = ide_json::installations(&ide_json::ide_json_path(config))?
+        .into_iter()
+        .find(|installation| installation.name == version)
+        .and_then(|installation| installation.path.parent().map(Path::to_path_buf));
+    let version_dir = listed.unwrap_or_else(|| {
+        idf_im_lib::expand_tilde(config.path.clone().unwrap_or_default().as_path()).join(version)
+    });
+    if version_dir.join("esp-idf").exists() {
+        Ok(version_dir)
+    } else {
+        Err(t!(
+            "update.not_installed",
+            version = version,
+            path = version_dir.display()
+        )
+        .to_string())
+    }
+}
+
+/// Updates the installed `version` to a newer release, or a branch to its
+/// latest commit.
+///
+/// The new version is prepared in a staging directory, see
+/// [`VersionTransaction`]: it starts from a local clone of the old one and
+/// its tool archives, and without `keep_old` its installed tools, so that
+/// only the tools which changed in tools.json are installed. The old version
+/// is only replaced once everything succeeded, and is left as it was
+/// otherwise. A release ends up in `<path>/<new version>`, a branch in the
+/// folder it was in.
+pub async fn update_version(
+    mut config: Settings,
+    version: &str,
+    options: &UpdateOptions,
+) -> Result<String, String> {
+    let version_dir = find_version_dir(&config, version)?;
+    let install_path = version_dir.parent().unwrap().to_path_buf();
+    let install_lock = DirLock::acquire(&install_path, options.wait_for_lock).await?;
+
+    let repo = Repository::open(version_dir.join("esp-idf")).map_err(git_error)?;
+    let Some(target) = select_target(&repo, version, options.to.as_deref())? else {
+        info!("{}", t!("update.up_to_date", version = version));
+        return Ok(version.to_string());
+    };
+    drop(repo);
+    let new_version = target.name().to_string();
+    let new_dir = install_path.join(&new_version);
+    if new_dir != version_dir && new_dir.exists() {
+        return Err(t!(
+            "update.exists",
+            version = &new_version,
+            path = new_dir.display()
+        )
+        .to_string());
+    }
+    info!(
+        "{}",
+        t!("update.started", version = version, target = &new_version)
+    );
+    let keep_old = options.keep_old && new_dir != version_dir;
+
+    let installing = interrupt::Installing::start();
+    let mut transaction = VersionTransaction::begin(&install_path, &new_version, false)?;
+    // a branch or a reinstalled release replaces the old version, which is
+    // moved aside by the transaction
+    let old_dir = transaction
+        .previous_dir()
+        .unwrap_or(&version_dir)
+        .to_path_buf();
+    if let Err(err) = update_in_staging(
+        &mut config,
+        &target,
+        &old_dir,
+        &mut transaction,
+        keep_old,
+        options,
+    )
+    .await
+    {
+        transaction.rollback(false);
+        return Err(err);
+    }
+    transaction.commit()?;
+    drop(installing);
+
+    let ide_json_path = ide_json::ide_json_path(&config);
+    let ide_json_dir = ide_json_path.parent().unwrap();
+    let _ide_json_lock = if install_lock.covers(ide_json_dir) {
+        None
+    } else {
+        Some(DirLock::acquire(ide_json_dir, options.wait_for_lock).await?)
+    };
+    config.path = Some(install_path.clone());
+    config.idf_path = Some(new_dir.join("esp-idf"));
+    config.idf_versions = Some(vec![new_version.clone()]);
+    ide_json::remove_installation(&ide_json_path, &new_version)?;
+    if !keep_old {
+        ide_json::remove_installation(&ide_json_path, version)?;
+    }
+    config
+        .save_esp_ide_json(ide_json_path.to_str().unwrap())
+        .map_err(|err| err.to_string())?;
+
+    // the old release is removed once nothing refers to it anymore
+    if !keep_old && new_dir != version_dir {
+        if let Err(err) = fs::remove_dir_all(&version_dir) {
+            warn!("Failed to remove {}: {}", version_dir.display(), err);
+        }
+        let old_script = install_path.join(format!("activate_idf_{}.sh", version));
+        if old_script.exists() {
+            if let Err(err) = fs::remove_file(&old_script) {
+                warn!("Failed to remove {}: {}", old_script.display(), err);
+            }
+        }
+    }
+
+    info!(
+        "{}",
+        t!("update.finished", version = version, target = &new_version)
+    );
+    Ok(new_version)
+}
+
+/// Prepares `target` in the staging directory of `transaction` from the
+/// version in `old_dir`, moves it into place and sets up its python
+/// environment there.
+async fn update_in_staging(
+    config: &mut Settings,
+    target: &Target,
+    old_dir: &Path,
+    transaction: &mut VersionTransaction,
+    keep_old: bool,
+    options: &UpdateOptions,
+) -> Result<(), String> {
+    let new_version = target.name();
+    let staging_dir = transaction.current_dir().to_path_buf();
+    let final_dir = transaction.final_dir().to_path_buf();
+    let install_path = final_dir.parent().unwrap().to_path_buf();
+    // left by an interrupted run, the update starts over
+    if fs::read_dir(&staging_dir).is_ok_and(|mut entries| entries.next().is_some()) {
+        fs::remove_dir_all(&staging_dir)
+            .and_then(|_| fs::create_dir_all(&staging_dir))
+            .map_err(|err| format!("Failed to clean {}: {}", staging_dir.display(), err))?;
+    }
+
+    let download_folder = config
+        .tool_download_folder_name
+        .clone()
+        .unwrap_or(DEFAULT_TOOLS_DOWNLOAD_FOLDER.to_string());
+    let install_folder = config
+        .tool_install_folder_name
+        .clone()
+        .unwrap_or(DEFAULT_TOOLS_INSTALL_FOLDER.to_string());
+    let old_idf_path = old_dir.join("esp-idf");
+    let idf_path = staging_dir.join("esp-idf");
+    let download_dir = staging_dir.join(&download_folder);
+    let tools_dir = staging_dir.join(&install_folder);
+    let old_tools = read_tools_file(&old_idf_path, config)?;
+
+    clone_local(&old_idf_path, &idf_path).map_err(git_error)?;
+    link_archives(&old_dir.join(&download_folder), &download_dir)?;
+    if !keep_old {
+        link_tools(&old_dir.join(&install_folder), &tools_dir)?;
+    }
+
+    let repo = Repository::open(&idf_path).map_err(git_error)?;
+    let progress = CloneProgress::new();
+    let commit = fetch(&repo, target, &progress).map_err(git_error)?;
+    // verified before the sources are replaced
+    options.trusted_keys.verify_tag(
+        &idf_path,
+        new_version,
+        config.non_interactive.unwrap_or_default(),
+    )?;
+    checkout(&repo, target, commit, &progress).map_err(git_error)?;
+    if config.recurse_submodules.unwrap_or(true) {
+        let _step = Step::start("submodules");
+        let mirror = options.mirrors.git(config.idf_mirror.as_deref());
+        // versions installed with a reference share it for new submodules
+        let reference = Some(reference_dir(&install_path)).filter(|path| path.exists());
+        if options.skip_unused_submodules {
+            git::skip_unused_submodules(&repo, &config.target.clone().unwrap_or_default())
+                .map_err(git_error)?;
+        }
+        update_submodules(
+            &repo,
+            IDF_REPOSITORY_URL,
+            &mirror,
+            options.clone_depth,
+            reference.as_deref(),
+            &progress,
+        )
+        .map_err(git_error)?;
+    }
+    drop(progress);
+    drop(repo);
+    interrupt::check()?;
+
+    let new_tools = read_tools_file(&idf_path, config)?;
+    let tools = if keep_old {
+        new_tools.clone()
+    } else {
+        changed_tools(&old_tools, &new_tools)
+    };
+    if tools.tools.is_empty() {
+        info!("{}", t!("update.tools_unchanged"));
+    } else {
+        let names: Vec<&str> = tools.tools.iter().map(|tool| tool.name.as_str()).collect();
+        info!("{}", t!("update.tools_changed", tools = names.join(", ")));
+        idf_im_lib::ensure_path(tools_dir.to_str().unwrap()).map_err(|e| e.to_string())?;
+        wizard::install_tools(
+            config,
+            tools,
+            &download_dir,
+            &tools_dir,
+            &options.trusted_keys,
+            &options.mirrors,
+            options.python.as_deref(),
+        )
+        .await?;
+    }
+    interrupt::check()?;
+
+    // the python environment holds absolute paths, it is set up once the
+    // version is in its final location, see install_version
+    transaction.move_into_place()?;
+    let idf_path = final_dir.join("esp-idf");
+    let tools_dir = final_dir.join(&install_folder);
+    config.path = Some(install_path);
+    config.idf_path = Some(idf_path.clone());
+    config.idf_versions = Some(vec![new_version.to_string()]);
+    wizard::setup_python_environment(config, &idf_path, &tools_dir, options.python.as_deref())?;
+    interrupt::check()?;
+    wizard::post_install(config, &final_dir, new_version, &tools_dir, new_tools);
+    interrupt::check()?;
+    InstallState::mark_complete(&final_dir)
+}
@@ -0,0 +1,118 @@
+use std::fs;
+use std::path::Path;
+
+use rust_i18n::t;
+use serde_json::Value;
+
+/// `v5.3.2` as `(5, 3, 2)`, `v5.3` as `(5, 3, 0)`. Pre-releases like
+/// `v5.4-rc1` and branches like `master` are not releases.
+pub fn release_number(name: &str) -> Option<(u32, u32, u32)> {
+    let mut parts = name.strip_prefix('v')?.split('.');
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next()?.parse().ok()?;
+    let patch = match parts.next() {
+        Some(patch) => patch.parse().ok()?,
+        None => 0,
+    };
+    if parts.next().is_some() {
+        return None;
+    }
+    Some((major, minor, patch))
+}
+
+/// The newest release of the minor version `version` belongs to.
+pub fn newest_patch<'a>(version: &str, names: impl Iterator<Item = &'a str>) -> Option<String> {
+    let (major, minor, _) = release_number(version)?;
+    names
+        .filter_map(|name| release_number(name).map(|number| (number, name)))
+        .filter(|((ma, mi, _), _)| *ma == major && *mi == minor)
+        .max_by_key(|(number, _)| *number)
+        .map(|(_, name)| name.to_string())
+}
+
+/// The newest release of every minor version after the one of `version`,
+/// oldest first.
+pub fn newer_minors<'a>(version: &str, names: impl Iterator<Item = &'a str>) -> Vec<String> {
+    let Some((major, minor, _)) = release_number(version) else {
+        return vec![];
+    };
+    let mut newest: Vec<((u32, u32, u32), &str)> = vec![];
+    for (number, name) in names.filter_map(|name| release_number(name).map(|n| (n, name))) {
+        if (number.0, number.1) <= (major, minor) {
+            continue;
+        }
+        match newest
+            .iter_mut()
+            .find(|((ma, mi, _), _)| (*ma, *mi) == (number.0, number.1))
+        {
+            Some(entry) if entry.0 < number => *entry = (number, name),
+            Some(_) => {}
+            None => newest.push((number, name)),
+        }
+    }
+    newest.sort();
+    newest
+        .into_iter()
+        .map(|(_, name)| name.to_string())
+        .collect()
+}
+
+/// The ESP-IDF versions offered for installation.
+pub struct VersionsIndex {
+    pub releases: Vec<String>,
+    /// Versions the index marks as end of life, only known from a local file.
+    pub end_of_life: Vec<String>,
+}
+
+impl VersionsIndex {
+    /// The online index, as offered by the wizard.
+    pub async fn online() -> Result<Self, String> {
+        let releases = idf_im_lib::idf_versions::get_idf_names().await;
+        if releases.is_empty() {
+            return Err(t!("versions.index_unavailable").to_string());
+        }
+        Ok(VersionsIndex {
+            releases,
+            end_of_life: vec![],
+        })
+    }
+
+    /// A local stand-in for the online index, in the same format
+    /// (`{"VERSIONS": [{"name": "v5.3.2", "end_of_life": false, ...}]}`).
+    /// Old versions and pre-releases are skipped like in the online one.
+    pub fn from_file(path: &Path) -> Result<Self, String> {
+        let content = fs::read_to_string(path)
+            .map_err(|err| format!("Failed to read {}: {}", path.display(), err))?;
+        let json: Value = serde_json::from_str(&content)
+            .map_err(|err| format!("Failed to parse {}: {}", path.display(), err))?;
+        let flag = |entry: &Value, name: &str| entry[name].as_bool().unwrap_or(false);
+
+        let mut index = VersionsIndex {
+            releases: vec![],
+            end_of_life: vec![],
+        };
+        for entry in json["VERSIONS"].as_array().cloned().unwrap_or_default() {
+            let Some(name) = entry["name"].as_str() else {
+                continue;
+            };
+            if flag(&entry, "end_of_life") {
+                index.end_of_life.push(name.to_string());
+            } else if !flag(&entry, "old") && !flag(&entry, "pre_release") {
+                index.releases.push(name.to_string());
+            }
+        }
+        Ok(index)
+    }
+
+    /// Whether the minor version of the release `version` is no longer
+    /// supported: marked so, or not offered any more.
+    pub fn is_end_of_life(&self, version: &str) -> bool {
+        let Some((major, minor, _)) = release_number(version) else {
+            return false;
+        };
+        let same_minor = |name: &String| {
+            release_number(name).is_some_and(|(ma, mi, _)| ma == major && mi == minor)
+        };
+        self.end_of_life.iter().any(same_minor) || !self.releases.iter().any(same_minor)
+    }
+}
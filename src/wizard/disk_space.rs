@@ -0,0 +1,351 @@
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+
+use idf_im_lib::idf_tools::{self, ToolsFile};
+use idf_im_lib::settings::Settings;
+use indicatif::HumanBytes;
+use log::{debug, log, warn, Level};
+use rust_i18n::t;
+use serde_json::Value;
+use url::Url;
+
+use super::helpers::generic_confirm;
+use super::install_state::InstallState;
+use super::transaction;
+use super::DEFAULT_TOOLS_JSON_LOCATION;
+use crate::configuration::mirrors::{Mirrors, IDF_REPOSITORY_URL};
+use crate::network;
+
+const MB: u64 = 1024 * 1024;
+/// Assumed size of an ESP-IDF clone whose host doesn't report the size of
+/// the repository.
+const FALLBACK_REPOSITORY_SIZE: u64 = 1200 * MB;
+/// Rough size of the submodules, which are separate repositories on
+/// various hosts and are not queried one by one.
+const IDF_SUBMODULES_SIZE: u64 = 800 * MB;
+/// Assumed tool downloads of a version whose tools.json can't be read.
+const FALLBACK_TOOLS_DOWNLOAD_SIZE: u64 = 1500 * MB;
+/// The tools are mostly xz compressed toolchains, which take about this many
+/// times the size of their archive once extracted.
+const EXTRACTION_FACTOR: u64 = 4;
+const GITHUB_HOST: &str = "github.com";
+/// Raw files and the repository details of repositories on GitHub are
+/// served by other hosts.
+const GITHUB_RAW_URL: &str = "https://raw.githubusercontent.com";
+const GITHUB_API_URL: &str = "https://api.github.com/repos";
+
+struct VersionEstimate {
+    version: String,
+    repository: u64,
+    downloads: u64,
+}
+
+impl VersionEstimate {
+    fn extracted(&self) -> u64 {
+        self.downloads * EXTRACTION_FACTOR
+    }
+
+    /// The archives are kept next to the extracted tools.
+    fn total(&self) -> u64 {
+        self.repository + self.downloads + self.extracted()
+    }
+}
+
+/// The ESP-IDF repository on GitHub, or the one of the `idf_mirror`, as
+/// `(host, owner/name)` with the URL it was taken from.
+struct Repository {
+    url: String,
+    host: String,
+    path: String,
+}
+
+impl Repository {
+    fn new(mirrors: &Mirrors, idf_mirror: Option<&str>) -> Option<Self> {
+        let url = mirrors.git(idf_mirror).rewrite(IDF_REPOSITORY_URL);
+        let parsed = Url::parse(&url).ok()?;
+        Some(Repository {
+            host: parsed.host_str()?.to_string(),
+            path: parsed
+                .path()
+                .trim_matches('/')
+                .trim_end_matches(".git")
+                .to_string(),
+            url,
+        })
+    }
+
+    /// URL of `file` at the tag `version`. GitHub serves raw files from its
+    /// own host, Gitee, GitLab and Gitea-like forges under `<repo>/raw/`.
+    fn raw_file_url(&self, version: &str, file: &str) -> String {
+        if self.host == GITHUB_HOST {
+            format!("{}/{}/{}/{}", GITHUB_RAW_URL, self.path, version, file)
+        } else {
+            format!(
+                "{}/raw/{}/{}",
+                self.url.trim_end_matches('/').trim_end_matches(".git"),
+                version,
+                file
+            )
+        }
+    }
+
+    /// Size of the repository as reported by its host, only GitHub reports
+    /// it without credentials.
+    async fn size(&self, client: &reqwest::Client) -> Option<u64> {
+        if self.host != GITHUB_HOST {
+            return None;
+        }
+        let url = format!("{}/{}", GITHUB_API_URL, self.path);
+        let response = client
+            .get(&url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+        let content = match response {
+            Ok(response) => response.text().await.ok()?,
+            Err(err) => {
+                debug!("Failed to get the size of {}: {}", self.url, err);
+                return None;
+            }
+        };
+        // in kilobytes
+        let size = serde_json::from_str::<Value>(&content).ok()?["size"].as_u64()?;
+        Some(size * 1024)
+    }
+}
+
+/// Reads tools.json from an earlier, interrupted run of the version, or
+/// downloads the one of the version's tag from the ESP-IDF repository or its
+/// mirror.
+async fn read_tools_file(
+    config: &Settings,
+    repository: Option<&Repository>,
+    install_path: &Path,
+    version: &str,
+) -> Option<ToolsFile> {
+    let relative = config
+        .tools_json_file
+        .clone()
+        .unwrap_or(DEFAULT_TOOLS_JSON_LOCATION.to_string());
+    let local = [
+        transaction::staging_dir(install_path, version),
+        install_path.join(version),
+    ]
+    .into_iter()
+    .map(|dir| dir.join("esp-idf").join(&relative))
+    .find(|path| path.exists());
+
+    let path = match local {
+        Some(path) => path,
+        None => {
+            let url = repository?.raw_file_url(version, DEFAULT_TOOLS_JSON_LOCATION);
+            let download_dir = std::env::temp_dir().join("eim-tools-json").join(version);
+            idf_im_lib::ensure_path(download_dir.to_str().unwrap()).ok()?;
+            debug!(
+                "Downloading {} to estimate the size of the tools",
+                network::hide_credentials(&url)
+            );
+            // the progress is not shown, the receiver only keeps the channel open
+            let (progress_tx, _progress_rx) = mpsc::channel();
+            if let Err(err) = idf_im_lib::download_file(
+                &network::authenticated_url(&url),
+                download_dir.to_str().unwrap(),
+                progress_tx,
+            )
+            .await
+            {
+                debug!(
+                    "Failed to download {}: {}",
+                    network::hide_credentials(&url),
+                    network::hide_credentials(&err.to_string())
+                );
+                return None;
+            }
+            download_dir.join("tools.json")
+        }
+    };
+    match idf_tools::read_and_parse_tools_file(path.to_str().unwrap()) {
+        Ok(tools) => Some(tools),
+        Err(err) => {
+            debug!("Failed to parse {}: {}", path.display(), err);
+            None
+        }
+    }
+}
+
+/// Sum of the archive sizes of the tools for the selected targets, on the
+/// platform `python` reports. Without a known platform the largest archive
+/// of every tool is counted.
+fn tools_download_size(tools: ToolsFile, targets: &Vec<String>, python: Option<&str>) -> u64 {
+    let list = idf_tools::filter_tools_by_target(tools.tools, targets);
+    match idf_tools::get_platform_identification(python) {
+        Ok(platform) => idf_tools::get_download_link_by_platform(list, &platform)
+            .values()
+            .map(|download| download.size)
+            .sum(),
+        Err(_) => list
+            .iter()
+            .filter_map(|tool| {
+                tool.versions
+                    .iter()
+                    .filter(|version| version.status == "recommended")
+                    .flat_map(|version| version.downloads.values())
+                    .map(|download| download.size)
+                    .max()
+            })
+            .sum(),
+    }
+}
+
+async fn estimate_version(
+    config: &Settings,
+    repository: Option<&Repository>,
+    repository_size: u64,
+    install_path: &Path,
+    version: &str,
+    python: Option<&str>,
+) -> VersionEstimate {
+    let repository_size = if config.recurse_submodules.unwrap_or(true) {
+        repository_size + IDF_SUBMODULES_SIZE
+    } else {
+        repository_size
+    };
+    let downloads = match read_tools_file(config, repository, install_path, version).await {
+        Some(tools) => {
+            tools_download_size(tools, &config.target.clone().unwrap_or_default(), python)
+        }
+        None => {
+            debug!(
+                "tools.json of {} is not available, assuming {} of tool downloads",
+                version,
+                HumanBytes(FALLBACK_TOOLS_DOWNLOAD_SIZE)
+            );
+            FALLBACK_TOOLS_DOWNLOAD_SIZE
+        }
+    };
+    VersionEstimate {
+        version: version.to_string(),
+        repository: repository_size,
+        downloads,
+    }
+}
+
+/// Free space of the file system `path` will be created on.
+fn available_space(path: &Path) -> Option<u64> {
+    let existing = path.ancestors().find(|p| p.exists())?;
+    fs4::available_space(existing)
+        .map_err(|err| {
+            debug!(
+                "Failed to get the free space of {}: {}",
+                existing.display(),
+                err
+            )
+        })
+        .ok()
+}
+
+/// Compares the space the versions which are not installed yet will need
+/// with the free space at the installation path. Without enough space the
+/// user is asked whether to continue anyway; in non-interactive mode the
+/// installation is aborted unless `ignore` is set. The repository and
+/// tools.json are looked up where the `idf_mirror` points to, the tools are
+/// the ones of the platform of `python`.
+pub async fn check_disk_space(
+    config: &Settings,
+    mirrors: &Mirrors,
+    python: Option<&str>,
+    ignore: bool,
+) -> Result<(), String> {
+    let install_path: PathBuf = idf_im_lib::expand_tilde(config.path.clone().unwrap().as_path());
+    let missing: Vec<String> = config
+        .idf_versions
+        .clone()
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|version| !InstallState::is_complete(&install_path.join(version)))
+        .collect();
+    if missing.is_empty() {
+        return Ok(());
+    }
+
+    let repository = Repository::new(mirrors, config.idf_mirror.as_deref());
+    let reported_size = match (&repository, network::http_client()) {
+        (Some(repository), Ok(client)) => repository.size(&client).await,
+        _ => None,
+    };
+    let repository_size = reported_size.unwrap_or_else(|| {
+        debug!(
+            "The size of the ESP-IDF repository is not available, assuming {}",
+            HumanBytes(FALLBACK_REPOSITORY_SIZE)
+        );
+        FALLBACK_REPOSITORY_SIZE
+    });
+    let mut estimates = vec![];
+    for version in &missing {
+        estimates.push(
+            estimate_version(
+                config,
+                repository.as_ref(),
+                repository_size,
+                &install_path,
+                version,
+                python,
+            )
+            .await,
+        );
+    }
+    let Some(available) = available_space(&install_path) else {
+        warn!(
+            "{}",
+            t!("wizard.disk_space.unknown", path = install_path.display())
+        );
+        return Ok(());
+    };
+    let required: u64 = estimates.iter().map(VersionEstimate::total).sum();
+    let enough = required <= available;
+
+    let level = if enough { Level::Debug } else { Level::Warn };
+    for estimate in &estimates {
+        log!(
+            level,
+            "{}",
+            t!(
+                "wizard.disk_space.version",
+                version = &estimate.version,
+                repository = HumanBytes(estimate.repository),
+                downloads = HumanBytes(estimate.downloads),
+                extracted = HumanBytes(estimate.extracted())
+            )
+        );
+    }
+    log!(
+        level,
+        "{}",
+        t!(
+            "wizard.disk_space.total",
+            required = HumanBytes(required),
+            available = HumanBytes(available),
+            path = install_path.display()
+        )
+    );
+    if enough {
+        return Ok(());
+    }
+
+    if ignore {
+        warn!("{}", t!("wizard.disk_space.ignored"));
+        return Ok(());
+    }
+    if !config.non_interactive.unwrap_or_default()
+        && generic_confirm("wizard.disk_space.prompt").unwrap_or(false)
+    {
+        return Ok(());
+    }
+    Err(t!(
+        "wizard.disk_space.insufficient",
+        path = install_path.display(),
+        required = HumanBytes(required),
+        available = HumanBytes(available)
+    )
+    .to_string())
+}
@@ -1,12 +1,9 @@
 use console::Style;
 use dialoguer::{theme::ColorfulTheme, Confirm, Input, MultiSelect, Select};
-use indicatif::{ProgressBar, ProgressState, ProgressStyle};
+use indicatif::{ProgressBar, ProgressStyle};
 use log::debug;
 use rust_i18n::t;
-use std::{
-    fmt::Write,
-    time::{Duration, Instant},
-};
+use std::time::{Duration, Instant};
 
 pub fn run_with_spinner<F, T>(func: F) -> T
 where
@@ -41,7 +38,7 @@ pub fn generic_select(prompt_key: &str, options: &[&str]) -> Result<String, Stri
         .items(options)
         .default(0)
         .interact()
-        .map_err(|e| format!("Failed to select: {}", e))?;
+        .map_err(|e| t!("wizard.select.failed", error = e).to_string())?;
     Ok(options[selection].to_string())
 }
 
@@ -69,9 +66,9 @@ pub fn generic_multiselect(
         .items(options)
         .defaults(defaults)
         .interact()
-        .map_err(|e| format!("Failed to select: {}", e))?;
+        .map_err(|e| t!("wizard.select.failed", error = e).to_string())?;
     if selection.is_empty() {
-        return Err("You must select at least one option".to_string());
+        return Err(t!("wizard.select.empty").to_string());
     }
 
     Ok(selection.into_iter().map(|i| options[i].clone()).collect())
@@ -92,24 +89,5 @@ pub fn generic_input(prompt_key: &str, error_key: &str, default: &str) -> Result
         .with_prompt(t!(prompt_key))
         .default(default.to_string())
         .interact()
-        .map_err(|e| format!("{} :{:?}", t!(error_key), e))
-}
-
-pub fn create_progress_bar() -> ProgressBar {
-    let pb = ProgressBar::new(100);
-    pb.set_style(
-        ProgressStyle::with_template(
-            "{spinner:.green} [{elapsed_precise}] [{wide_bar:.cyan/blue}] ({eta})",
-        )
-        .unwrap()
-        .with_key("eta", |state: &ProgressState, w: &mut dyn Write| {
-            write!(w, "{:.1}s", state.eta().as_secs_f64()).unwrap()
-        })
-        .progress_chars("#>-"),
-    );
-    pb
-}
-
-pub fn update_progress_bar_number(pb: &ProgressBar, value: u64) {
-    pb.set_position(value);
+        .map_err(|e| t!(error_key, error = format!("{:?}", e)).to_string())
 }
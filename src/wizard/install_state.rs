@@ -0,0 +1,138 @@
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use log::{debug, warn};
+
+/// File in the version directory recording the finished steps.
+const STATE_FILE_NAME: &str = ".eim_install_state";
+
+/// Steps of installing one IDF version, in the order they are run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InstallStep {
+    Cloned,
+    ToolsDownloaded,
+    ToolsExtracted,
+    PythonEnvironment,
+    PostInstall,
+}
+
+impl InstallStep {
+    const ALL: [InstallStep; 5] = [
+        InstallStep::Cloned,
+        InstallStep::ToolsDownloaded,
+        InstallStep::ToolsExtracted,
+        InstallStep::PythonEnvironment,
+        InstallStep::PostInstall,
+    ];
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            InstallStep::Cloned => "cloned",
+            InstallStep::ToolsDownloaded => "tools_downloaded",
+            InstallStep::ToolsExtracted => "tools_extracted",
+            InstallStep::PythonEnvironment => "python_environment",
+            InstallStep::PostInstall => "post_install",
+        }
+    }
+
+    fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|step| step.as_str() == name)
+    }
+}
+
+impl fmt::Display for InstallStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Journal of one version installation, so that an interrupted or failed
+/// run can be resumed at the first step which did not finish.
+pub struct InstallState {
+    path: PathBuf,
+    completed: Vec<InstallStep>,
+    resumed: bool,
+}
+
+impl InstallState {
+    /// Reads the journal in `version_dir`, or starts a new one.
+    pub fn load(version_dir: &Path) -> Result<Self, String> {
+        let path = version_dir.join(STATE_FILE_NAME);
+        let mut state = InstallState {
+            path,
+            completed: vec![],
+            resumed: false,
+        };
+        match fs::read_to_string(&state.path) {
+            Ok(content) => {
+                state.resumed = true;
+                for line in content.lines().map(str::trim).filter(|l| !l.is_empty()) {
+                    match InstallStep::parse(line) {
+                        Some(step) => state.completed.push(step),
+                        None => warn!(
+                            "Unknown install step `{}` in {}",
+                            line,
+                            state.path.display()
+                        ),
+                    }
+                }
+                debug!(
+                    "Resuming installation, completed steps: {:?}",
+                    state.completed
+                );
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                // created right away, so that a later run knows that
+                // whatever it finds in the directory was left by us
+                fs::create_dir_all(version_dir).map_err(|e| e.to_string())?;
+                state.save()?;
+            }
+            Err(err) => return Err(format!("{}: {}", state.path.display(), err)),
+        }
+        Ok(state)
+    }
+
+    /// Whether `version_dir` holds a completely installed version.
+    pub fn is_complete(version_dir: &Path) -> bool {
+        fs::read_to_string(version_dir.join(STATE_FILE_NAME)).is_ok_and(|content| {
+            content
+                .lines()
+                .any(|line| line.trim() == InstallStep::PostInstall.as_str())
+        })
+    }
+
+    /// Records all steps as done, for a version installed without the wizard
+    /// (e.g. by `eim update`).
+    pub fn mark_complete(version_dir: &Path) -> Result<(), String> {
+        let mut state = Self::load(version_dir)?;
+        state.completed = InstallStep::ALL.to_vec();
+        state.save()
+    }
+
+    /// Whether a previous run already started installing this version.
+    pub fn is_resumed(&self) -> bool {
+        self.resumed
+    }
+
+    pub fn is_done(&self, step: InstallStep) -> bool {
+        self.completed.contains(&step)
+    }
+
+    pub fn mark_done(&mut self, step: InstallStep) -> Result<(), String> {
+        if !self.is_done(step) {
+            self.completed.push(step);
+        }
+        self.save()
+    }
+
+    fn save(&self) -> Result<(), String> {
+        let content: String = self
+            .completed
+            .iter()
+            .map(|step| format!("{}\n", step))
+            .collect();
+        fs::write(&self.path, content)
+            .map_err(|err| format!("Failed to write {}: {}", self.path.display(), err))
+    }
+}
@@ -1,7 +1,7 @@
 use dialoguer::FolderSelect;
 use idf_im_lib::idf_tools::ToolsFile;
 use idf_im_lib::settings::Settings;
-use idf_im_lib::{ensure_path, DownloadProgress, ProgressMessage};
+use idf_im_lib::{ensure_path, DownloadProgress};
 use indicatif::{ProgressBar, ProgressState, ProgressStyle};
 use log::{debug, error, info, warn};
 use rust_i18n::t;
@@ -14,39 +14,58 @@ use std::{
     path::{Path, PathBuf},
 };
 
+use crate::configuration::mirrors::{Mirrors, RewriteRules, IDF_REPOSITORY_URL};
+use crate::git;
+use crate::ide_json;
+use crate::interrupt;
+use crate::lock::DirLock;
+use crate::logging::{self, Step};
+use crate::network;
+use crate::python;
+use crate::signatures::TrustedKeys;
+
 // maybe move the default values to the config too?
-const DEFAULT_TOOLS_DOWNLOAD_FOLDER: &str = "dist";
-const DEFAULT_TOOLS_INSTALL_FOLDER: &str = "tools";
-const DEFAULT_TOOLS_JSON_LOCATION: &str = "tools/tools.json";
+pub const DEFAULT_TOOLS_DOWNLOAD_FOLDER: &str = "dist";
+pub const DEFAULT_TOOLS_INSTALL_FOLDER: &str = "tools";
+pub const DEFAULT_TOOLS_JSON_LOCATION: &str = "tools/tools.json";
 const DEFAULT_IDF_TOOLS_PY_LOCATION: &str = "./tools/idf_tools.py";
 
+mod disk_space;
 pub mod helpers;
-use helpers::{
-    create_progress_bar, create_theme, generic_confirm, generic_input, update_progress_bar_number,
-};
+pub mod install_state;
+use helpers::{create_theme, generic_confirm, generic_input};
+use install_state::{InstallState, InstallStep};
 
 mod prompts;
 use prompts::*;
+pub mod transaction;
+use transaction::VersionTransaction;
 
 async fn download_tools(
     tools_file: ToolsFile,
     selected_chip: Vec<String>,
     destination_path: &str,
-    mirror: Option<&str>,
-) -> Vec<String> {
+    mirror: &RewriteRules,
+    trusted_keys: &TrustedKeys,
+    non_interactive: bool,
+    python: Option<&str>,
+) -> Result<Vec<String>, String> {
+    let _step = Step::start("download_tools");
     let tool_name_list: Vec<String> = tools_file
         .tools
         .iter()
         .map(|tool| tool.name.clone())
         .collect();
     info!(
-        "{}: {:?}",
-        t!("wizard.tools_download.progress"),
-        tool_name_list
+        "{}",
+        t!(
+            "wizard.tools_download.progress",
+            tools = tool_name_list.join(", ")
+        )
     );
     let list = idf_im_lib::idf_tools::filter_tools_by_target(tools_file.tools, &selected_chip);
 
-    let platform = match idf_im_lib::idf_tools::get_platform_identification(None) {
+    let platform = match idf_im_lib::idf_tools::get_platform_identification(python) {
         Ok(platform) => platform,
         Err(err) => {
             if std::env::consts::OS == "windows" {
@@ -64,25 +83,35 @@ async fn download_tools(
                     Ok(platform) => platform,
                     Err(err) => {
                         error!("Unable to identify platform: {}", err);
-                        panic!("{}.  {:?}", t!("wizard.tools_platform_error"), err);
+                        panic!(
+                            "{}",
+                            t!("wizard.tools_platform_error", error = format!("{:?}", err))
+                        );
                     }
                 }
             } else {
-                panic!("{}.  {:?}", t!("wizard.tools_platform_error"), err);
+                panic!(
+                    "{}",
+                    t!("wizard.tools_platform_error", error = format!("{:?}", err))
+                );
             }
         }
     };
     debug!("Python platform: {}", platform);
-    let download_links = idf_im_lib::idf_tools::change_links_donwanload_mirror(
-        idf_im_lib::idf_tools::get_download_link_by_platform(list, &platform),
-        // Some("https://dl.espressif.com/github_assets"), // this switches mirror, should be parametrized
-        mirror,
-    )
-    .into_iter()
-    .collect::<Vec<_>>();
+    let download_links = idf_im_lib::idf_tools::get_download_link_by_platform(list, &platform)
+        .into_iter()
+        .map(|(tool_name, mut download_link)| {
+            download_link.url = mirror.rewrite(&download_link.url);
+            (tool_name, download_link)
+        })
+        .collect::<Vec<_>>();
     let mut downloaded_tools: Vec<String> = vec![];
     for (tool_name, download_link) in download_links.iter() {
-        info!("{}: {}", t!("wizard.tool_download.progress"), tool_name);
+        if interrupt::requested() {
+            break;
+        }
+        logging::set_context("tool", tool_name);
+        info!("{}", t!("wizard.tool_download.progress", tool = tool_name));
 
         let (progress_tx, progress_rx) = mpsc::channel();
 
@@ -103,9 +132,12 @@ async fn download_tools(
             full_file_path.to_str().unwrap(),
         ) {
             Ok(true) => {
-                downloaded_tools.push(filename.to_string()); // add it to the list for extraction even if it's already downloaded
                 info!("{}", t!("wizard.tool_file.present"));
                 progress_bar.finish();
+                trusted_keys
+                    .verify_archive(&download_link.url, &full_file_path, non_interactive)
+                    .await?;
+                downloaded_tools.push(filename.to_string()); // add it to the list for extraction even if it's already downloaded
                 continue;
             }
             _ => {
@@ -126,7 +158,11 @@ async fn download_tools(
                             break;
                         }
                         DownloadProgress::Error(err) => {
-                            log::error!("Error downloading {}: {}", tn, err);
+                            log::error!(
+                                "Error downloading {}: {}",
+                                tn,
+                                network::hide_credentials(&err)
+                            );
                             break;
                         }
                     }
@@ -134,15 +170,24 @@ async fn download_tools(
             })
         };
 
-        match idf_im_lib::download_file(&download_link.url, destination_path, progress_tx).await {
+        match idf_im_lib::download_file(
+            &network::authenticated_url(&download_link.url),
+            destination_path,
+            progress_tx,
+        )
+        .await
+        {
             Ok(_) => {
                 downloaded_tools.push(filename.to_string());
                 progress_bar.finish();
-                info!("{} {}", t!("wizard.tool.downloaded"), tool_name);
+                info!("{}", t!("wizard.tool.downloaded", tool = tool_name));
             }
             Err(err) => {
-                error!("{}: {}", t!("wizard.tool.download_failed"), tool_name);
-                error!("Error: {:?}", err);
+                error!("{}", t!("wizard.tool.download_failed", tool = tool_name));
+                error!(
+                    "Error: {}",
+                    network::hide_credentials(&format!("{:?}", err))
+                );
                 panic!();
             }
         }
@@ -152,48 +197,58 @@ async fn download_tools(
             full_file_path.to_str().unwrap(),
         ) {
             Ok(true) => {
-                continue;
+                trusted_keys
+                    .verify_archive(&download_link.url, &full_file_path, non_interactive)
+                    .await?;
             }
             _ => {
                 error!("{}", t!("wizard.tool.corupted"));
                 match fs::remove_file(&full_file_path) {
                     Ok(_) => {
                         error!(
-                            "{}: {}",
-                            t!("wizard.tool.removed"),
-                            full_file_path.to_str().unwrap()
+                            "{}",
+                            t!("wizard.tool.removed", path = full_file_path.display())
                         );
                     }
                     Err(err) => {
                         error!(
-                            "{}: {}: {}",
-                            t!("wizard.tool.remove_failed"),
-                            full_file_path.to_str().unwrap(),
-                            err
+                            "{}",
+                            t!(
+                                "wizard.tool.remove_failed",
+                                path = full_file_path.display(),
+                                error = err
+                            )
                         );
                     }
                 };
             }
         }
     }
-    downloaded_tools
+    logging::clear_context("tool");
+    Ok(downloaded_tools)
 }
 
 fn extract_tools(tools: Vec<String>, source_path: &str, destination_path: &str) {
+    let _step = Step::start("extract_tools");
     for tool in tools.iter() {
+        if interrupt::requested() {
+            break;
+        }
+        logging::set_context("tool", tool);
         let mut archive_path = PathBuf::from(source_path);
         archive_path.push(tool);
         let out = idf_im_lib::decompress_archive(archive_path.to_str().unwrap(), destination_path);
         match out {
             Ok(_) => {
-                info!("{}: {}", t!("wizard.tool.extracted"), tool);
+                info!("{}", t!("wizard.tool.extracted", tool = tool));
             }
             Err(err) => {
                 error!("{:?}", err);
-                panic!("{}: {}", t!("wizard.tool.extract_failed"), tool)
+                panic!("{}", t!("wizard.tool.extract_failed", tool = tool))
             }
         }
     }
+    logging::clear_context("tool");
 }
 
 fn add_to_shell_rc(content: &str) -> Result<(), String> {
@@ -253,8 +308,15 @@ async fn select_targets_and_versions(mut config: Settings) -> Result<Settings, S
 pub struct DownloadConfig {
     pub idf_path: String,
     pub idf_version: String,
-    pub idf_mirror: Option<String>,
+    /// Git rules of the `idf_mirror`.
+    pub mirror: RewriteRules,
     pub recurse_submodules: Option<bool>,
+    /// Number of commits of history to clone, all of it if `None`.
+    pub clone_depth: Option<u32>,
+    /// Skip the submodules none of these targets needs.
+    pub submodule_targets: Option<Vec<String>>,
+    /// Reference repository shared by the installed versions.
+    pub reference: Option<PathBuf>,
     pub non_interactive: Option<bool>,
 }
 
@@ -262,6 +324,7 @@ pub enum DownloadError {
     PathCreationFailed(String),
     DownloadFailed(String),
     UserCancelled,
+    Interrupted,
 }
 
 fn handle_download_error(err: git2::Error) -> Result<(), DownloadError> {
@@ -276,64 +339,31 @@ fn handle_download_error(err: git2::Error) -> Result<(), DownloadError> {
 }
 
 pub fn download_idf(config: DownloadConfig) -> Result<(), DownloadError> {
+    let _step = Step::start("download_idf");
     idf_im_lib::ensure_path(&config.idf_path)
         .map_err(|err| DownloadError::PathCreationFailed(err.to_string()))?;
 
-    let (tx, rx) = mpsc::channel();
-
-    // Spawn a thread to handle progress bar updates
-    let handle = thread::spawn(move || {
-        let mut progress_bar = create_progress_bar();
-
-        loop {
-            match rx.recv() {
-                Ok(ProgressMessage::Finish) => {
-                    update_progress_bar_number(&progress_bar, 100);
-                    progress_bar.finish();
-                    progress_bar = create_progress_bar();
-                }
-                Ok(ProgressMessage::Update(value)) => {
-                    update_progress_bar_number(&progress_bar, value);
-                }
-                Err(_) => {
-                    break;
-                }
-            }
-        }
-    });
-
     let tag = if config.idf_version == "master" {
         None
     } else {
         Some(config.idf_version)
     };
-    let group_name = config
-        .idf_mirror
-        .as_deref()
-        .map(|mirror| {
-            if mirror.contains("https://gitee.com/") {
-                Some("EspressifSystems")
-            } else {
-                None
-            }
-        })
-        .flatten();
-
-    match idf_im_lib::get_esp_idf_by_tag_name(
-        &config.idf_path,
-        tag.as_deref(),
-        tx,
-        config.idf_mirror.as_deref(),
-        group_name,
-        config.recurse_submodules.unwrap_or(true),
-    ) {
+    let options = git::CloneOptions {
+        mirror: &config.mirror,
+        tag: tag.as_deref(),
+        recurse_submodules: config.recurse_submodules.unwrap_or(true),
+        depth: config.clone_depth,
+        targets: config.submodule_targets.as_deref(),
+        reference: config.reference.as_deref(),
+    };
+    match git::clone(IDF_REPOSITORY_URL, Path::new(&config.idf_path), &options) {
         Ok(_) => {
             debug!("{}", t!("wizard.idf.success"));
-            handle.join().unwrap();
             Ok(())
         }
+        Err(err) if git::is_interrupted(&err) => Err(DownloadError::Interrupted),
         Err(err) => {
-            if config.non_interactive == Some(true) {
+            if config.non_interactive == Some(true) && err.code() == git2::ErrorCode::Exists {
                 Ok(())
             } else {
                 handle_download_error(err)
@@ -409,27 +439,80 @@ fn validate_tools_json_file(tools_json_file: &Path, config: &mut Settings) -> St
     }
 }
 
+/// Removes what an interrupted run left behind, so the step can start over.
+fn remove_incomplete(path: &Path) -> Result<(), String> {
+    if path.exists() {
+        info!("{}", t!("wizard.resume.cleanup", path = path.display()));
+        fs::remove_dir_all(path).map_err(|err| err.to_string())?;
+    }
+    Ok(())
+}
+
 async fn download_and_extract_tools(
     config: &Settings,
     tools: &ToolsFile,
     download_dir: &PathBuf,
     install_dir: &PathBuf,
+    state: &mut InstallState,
+    options: &InstallOptions,
+    python: &str,
 ) -> Result<(), String> {
+    // already downloaded archives are kept, their checksum is verified again
+    let extraction_started = state.is_done(InstallStep::ToolsDownloaded);
     let downloaded_tools_list = download_tools(
         tools.clone(),
         config.target.clone().unwrap(),
         download_dir.to_str().unwrap(),
-        config.mirror.as_deref(),
+        &options.mirrors.tools(config.mirror.as_deref()),
+        &options.trusted_keys,
+        config.non_interactive.unwrap_or_default(),
+        Some(python),
     )
-    .await;
+    .await?;
+    interrupt::check()?;
+    state.mark_done(InstallStep::ToolsDownloaded)?;
 
+    if extraction_started {
+        remove_incomplete(install_dir)?;
+        ensure_path(install_dir.to_str().unwrap()).map_err(|err| err.to_string())?;
+    }
     extract_tools(
         downloaded_tools_list,
         download_dir.to_str().unwrap(),
         install_dir.to_str().unwrap(),
     );
+    interrupt::check()?;
+    state.mark_done(InstallStep::ToolsExtracted)
+}
 
-    Ok(())
+/// Downloads and extracts `tools` without a journal, used by `eim update`
+/// for the tools which changed.
+pub async fn install_tools(
+    config: &Settings,
+    tools: ToolsFile,
+    download_dir: &Path,
+    install_dir: &Path,
+    trusted_keys: &TrustedKeys,
+    mirrors: &Mirrors,
+    python: Option<&str>,
+) -> Result<(), String> {
+    let downloaded_tools_list = download_tools(
+        tools,
+        config.target.clone().unwrap(),
+        download_dir.to_str().unwrap(),
+        &mirrors.tools(config.mirror.as_deref()),
+        trusted_keys,
+        config.non_interactive.unwrap_or_default(),
+        python,
+    )
+    .await?;
+    interrupt::check()?;
+    extract_tools(
+        downloaded_tools_list,
+        download_dir.to_str().unwrap(),
+        install_dir.to_str().unwrap(),
+    );
+    interrupt::check()
 }
 
 fn get_and_validate_idf_tools_path(
@@ -461,7 +544,7 @@ fn get_and_validate_idf_tools_path(
             .folder(idf_path.to_str().unwrap())
             .file(true)
             .interact()
-            .map_err(|e| format!("Failed to select: {}", e))?;
+            .map_err(|e| t!("wizard.select.failed", error = e).to_string())?;
 
         if fs::metadata(&idf_tools_py_select).is_ok() {
             idf_tools_path = PathBuf::from(&idf_tools_py_select);
@@ -474,147 +557,335 @@ fn get_and_validate_idf_tools_path(
     Ok(idf_tools_path)
 }
 
-pub async fn run_wizzard_run(mut config: Settings) -> Result<(), String> {
-    debug!("Config entering wizard: {:?}", config);
-
-    // Check prerequisites
-    check_and_install_prerequisites(
-        config.non_interactive.unwrap_or_default(),
-        config.install_all_prerequisites.unwrap_or_default(),
-    )?;
-
-    // Python sanity check
-    check_and_install_python(
-        config.non_interactive.unwrap_or_default(),
-        config.install_all_prerequisites.unwrap_or_default(),
-    )?;
-
-    // select target & idf version
-    config = select_targets_and_versions(config).await?;
-
-    // mirrors select
-    config = select_mirrors(config)?;
-
-    config = select_installation_path(config)?;
-
-    // Multiple version starts here
+/// Installs one version, see [`VersionTransaction`] for where it happens.
+async fn install_version(
+    config: &mut Settings,
+    idf_version: &str,
+    transaction: &mut VersionTransaction,
+    options: &InstallOptions,
+    python: &str,
+) -> Result<(), String> {
+    let staging_path = transaction.current_dir().to_path_buf();
+    let idf_path = staging_path.join("esp-idf");
+    let mut state = InstallState::load(&staging_path)?;
+    let skip = |step: InstallStep| {
+        info!(
+            "{}",
+            t!("wizard.resume.skip", step = step, version = &idf_version)
+        );
+    };
 
-    for idf_version in config.idf_versions.clone().unwrap() {
-        let mut version_instalation_path = config.path.clone().unwrap();
-        version_instalation_path = idf_im_lib::expand_tilde(version_instalation_path.as_path());
-        version_instalation_path.push(&idf_version);
-        let mut idf_path = version_instalation_path.clone();
-        idf_path.push("esp-idf");
-        config.idf_path = Some(idf_path.clone()); // todo: list all of the paths
-        idf_im_lib::add_path_to_path(idf_path.to_str().unwrap());
-
-        // download idf
+    // download idf
+    if state.is_done(InstallStep::Cloned) {
+        skip(InstallStep::Cloned);
+    } else {
+        if state.is_resumed() {
+            remove_incomplete(&idf_path)?;
+        }
         let download_config = DownloadConfig {
             idf_path: idf_path.to_str().unwrap().to_string(),
             idf_version: idf_version.to_string(),
-            idf_mirror: config.idf_mirror.clone(),
+            mirror: options.mirrors.git(config.idf_mirror.as_deref()),
             recurse_submodules: config.recurse_submodules,
+            clone_depth: options.clone_depth,
+            submodule_targets: options
+                .skip_unused_submodules
+                .then(|| config.target.clone().unwrap_or_default()),
+            reference: options.shared_git_objects.then(|| {
+                git::reference_dir(&idf_im_lib::expand_tilde(
+                    config.path.clone().unwrap().as_path(),
+                ))
+            }),
             non_interactive: config.non_interactive,
         };
 
         match download_idf(download_config) {
             Ok(_) => {
-                debug!("{}", t!("wizard.idf.sucess"));
+                debug!("{}", t!("wizard.idf.success"));
             }
             Err(DownloadError::PathCreationFailed(err)) => {
-                error!("{} {:?}", t!("wizard.idf.path_creation_failure"), err);
+                error!("{}", t!("wizard.idf.path_creation_failure", error = err));
                 return Err(err);
             }
             Err(DownloadError::DownloadFailed(err)) => {
-                error!("{} {:?}", t!("wizard.idf.failure"), err);
+                error!("{}", t!("wizard.idf.failure", error = err));
                 return Err(err);
             }
             Err(DownloadError::UserCancelled) => {
                 error!("{}", t!("wizard.idf.user_cancelled"));
                 return Err("User cancelled the operation".to_string());
             }
+            Err(DownloadError::Interrupted) => return Err(interrupt::stopped()),
         }
-        // setup tool directories
-
-        let tool_download_directory = setup_directory(
-            config.wizard_all_questions.clone(),
-            &version_instalation_path,
-            &mut config.tool_download_folder_name,
-            "wizard.tools.download.prompt",
-            DEFAULT_TOOLS_DOWNLOAD_FOLDER,
-        )?;
-
-        // Setup install directory
-        let tool_install_directory = setup_directory(
-            config.wizard_all_questions.clone(),
-            &version_instalation_path,
-            &mut config.tool_install_folder_name,
-            "wizard.tools.install.prompt",
-            DEFAULT_TOOLS_INSTALL_FOLDER,
-        )?;
+        state.mark_done(InstallStep::Cloned)?;
+        interrupt::check()?;
+    }
+    // also when resuming, the clone may have changed since the last run
+    options.trusted_keys.verify_tag(
+        &idf_path,
+        idf_version,
+        config.non_interactive.unwrap_or_default(),
+    )?;
+    // setup tool directories
+
+    let tool_download_directory = setup_directory(
+        config.wizard_all_questions.clone(),
+        &staging_path,
+        &mut config.tool_download_folder_name,
+        "wizard.tools.download.prompt",
+        DEFAULT_TOOLS_DOWNLOAD_FOLDER,
+    )?;
 
-        idf_im_lib::add_path_to_path(tool_install_directory.to_str().unwrap());
+    // Setup install directory
+    let tool_install_directory = setup_directory(
+        config.wizard_all_questions.clone(),
+        &staging_path,
+        &mut config.tool_install_folder_name,
+        "wizard.tools.install.prompt",
+        DEFAULT_TOOLS_INSTALL_FOLDER,
+    )?;
 
-        // tools_json_file
+    // tools_json_file
 
-        let tools_json_file = get_tools_json_path(&mut config, &idf_path);
-        let validated_file = validate_tools_json_file(&tools_json_file, &mut config);
+    let tools_json_file = get_tools_json_path(config, &idf_path);
+    let validated_file = validate_tools_json_file(&tools_json_file, config);
 
-        debug!("Tools json file: {}", tools_json_file.display());
+    debug!("Tools json file: {}", tools_json_file.display());
 
-        let tools = idf_im_lib::idf_tools::read_and_parse_tools_file(&validated_file)
-            .map_err(|err| format!("{}: {}", t!("wizard.tools_json.unparsable"), err))?;
+    let tools = idf_im_lib::idf_tools::read_and_parse_tools_file(&validated_file)
+        .map_err(|err| t!("wizard.tools_json.unparsable", e = err).to_string())?;
 
+    if state.is_done(InstallStep::ToolsExtracted) {
+        skip(InstallStep::ToolsExtracted);
+    } else {
         download_and_extract_tools(
-            &&config,
+            &config,
             &tools,
             &tool_download_directory,
             &tool_install_directory,
+            &mut state,
+            options,
+            python,
         )
         .await?;
+    }
+
+    // the python environment holds absolute paths, it is set up once the
+    // version is in its final location
+    transaction.move_into_place()?;
+    let version_instalation_path = transaction.current_dir().to_path_buf();
+    let idf_path = version_instalation_path.join("esp-idf");
+    let tool_install_directory = tool_install_directory
+        .strip_prefix(&staging_path)
+        .map(|relative| version_instalation_path.join(relative))
+        .unwrap_or(tool_install_directory.clone());
+    let mut state = InstallState::load(&version_instalation_path)?;
+
+    if state.is_done(InstallStep::PythonEnvironment) {
+        skip(InstallStep::PythonEnvironment);
+    } else {
+        setup_python_environment(config, &idf_path, &tool_install_directory, Some(python))?;
+        state.mark_done(InstallStep::PythonEnvironment)?;
+        interrupt::check()?;
+    }
+
+    if state.is_done(InstallStep::PostInstall) {
+        skip(InstallStep::PostInstall);
+    } else {
+        post_install(
+            config,
+            &version_instalation_path,
+            idf_version,
+            &tool_install_directory,
+            tools,
+        );
+        state.mark_done(InstallStep::PostInstall)?;
+        interrupt::check()?;
+    }
+
+    config.idf_path = Some(version_instalation_path.join("esp-idf")); // todo: list all of the paths
+    Ok(())
+}
+
+/// Runs idf_tools.py of `idf_path`, which installs the python environment
+/// and the IDF features, with `python` or else the `python3` of PATH.
+pub fn setup_python_environment(
+    config: &mut Settings,
+    idf_path: &PathBuf,
+    tool_install_directory: &PathBuf,
+    python: Option<&str>,
+) -> Result<(), String> {
+    let _step = Step::start("idf_tools_py");
+    idf_im_lib::add_path_to_path(idf_path.to_str().unwrap());
+    idf_im_lib::add_path_to_path(tool_install_directory.to_str().unwrap());
+    if let Some(python) = python {
+        python::use_interpreter(Path::new(python), tool_install_directory)?;
+    }
+
+    let env_vars = idf_im_lib::setup_environment_variables(tool_install_directory, idf_path)?;
 
-        let env_vars = idf_im_lib::setup_environment_variables(&tool_install_directory, &idf_path)?;
+    let idf_tools_path = get_and_validate_idf_tools_path(config, idf_path)?;
 
-        let idf_tools_path = get_and_validate_idf_tools_path(&mut config, &idf_path)?;
+    if config.idf_features.is_some() {
+        let features = config.idf_features.clone().unwrap();
+        idf_im_lib::python_utils::run_idf_tools_py_with_features(
+            idf_tools_path.to_str().unwrap(),
+            &env_vars,
+            &features,
+        )?;
+    } else {
+        idf_im_lib::python_utils::run_idf_tools_py(idf_tools_path.to_str().unwrap(), &env_vars)?;
+    }
+    Ok(())
+}
 
-        if config.idf_features.is_some() {
-            let features = config.idf_features.clone().unwrap();
-            idf_im_lib::python_utils::run_idf_tools_py_with_features(
-                idf_tools_path.to_str().unwrap(),
-                &env_vars,
-                &features,
-            )?;
+/// Creates the activation scripts (and desktop icons on Windows) of the
+/// version installed in `version_path`.
+pub fn post_install(
+    config: &Settings,
+    version_path: &Path,
+    idf_version: &str,
+    tool_install_directory: &Path,
+    tools: ToolsFile,
+) {
+    let _step = Step::start("post_install");
+    let idf_path = version_path.join("esp-idf");
+    let export_paths = idf_im_lib::idf_tools::get_tools_export_paths(
+        tools,
+        config.target.clone().unwrap().clone(),
+        tool_install_directory.join("tools").to_str().unwrap(),
+    )
+    .into_iter()
+    .map(|p| {
+        if std::env::consts::OS == "windows" {
+            idf_im_lib::replace_unescaped_spaces_win(&p)
         } else {
-            idf_im_lib::python_utils::run_idf_tools_py(
-                idf_tools_path.to_str().unwrap(),
-                &env_vars,
-            )?;
+            p
         }
+    })
+    .collect();
+
+    idf_im_lib::single_version_post_install(
+        version_path.to_str().unwrap(),
+        idf_path.to_str().unwrap(),
+        idf_version,
+        tool_install_directory.to_str().unwrap(),
+        export_paths,
+    );
+}
 
-        let export_paths = idf_im_lib::idf_tools::get_tools_export_paths(
-            tools,
-            config.target.clone().unwrap().clone(),
-            tool_install_directory.join("tools").to_str().unwrap(),
+/// Options of the installation which are not part of the settings.
+#[derive(Debug, Default)]
+pub struct InstallOptions {
+    /// Keep the files of a failed version instead of rolling back.
+    pub keep_failed: bool,
+    /// Install even if the estimated disk space is not available.
+    pub ignore_disk_space: bool,
+    /// Wait for another eim process to release the installation path.
+    pub wait_for_lock: bool,
+    pub trusted_keys: TrustedKeys,
+    pub mirrors: Mirrors,
+    /// Number of commits of ESP-IDF history to clone, all of it if `None`.
+    pub clone_depth: Option<u32>,
+    /// Skip the submodules the selected targets don't need.
+    pub skip_unused_submodules: bool,
+    /// Share the git objects of the installed versions.
+    pub shared_git_objects: bool,
+    /// Python interpreter to install with, searched for if `None`.
+    pub python: Option<String>,
+}
+
+pub async fn run_wizzard_run(mut config: Settings, options: &InstallOptions) -> Result<(), String> {
+    debug!("Config entering wizard: {:?}", config);
+
+    // Check prerequisites
+    {
+        let _step = Step::start("prerequisites");
+        check_and_install_prerequisites(
+            config.non_interactive.unwrap_or_default(),
+            config.install_all_prerequisites.unwrap_or_default(),
+        )?;
+    }
+
+    // Python sanity check
+    let python = {
+        let _step = Step::start("python");
+        check_and_install_python(
+            config.non_interactive.unwrap_or_default(),
+            config.install_all_prerequisites.unwrap_or_default(),
+            options.python.as_deref(),
+        )?
+    };
+
+    // select target & idf version
+    config = select_targets_and_versions(config).await?;
+
+    // mirrors select
+    config = select_mirrors(config)?;
+
+    config = select_installation_path(config)?;
+
+    // held until the end, other eim processes must not touch the same path
+    let install_lock = DirLock::acquire(
+        &idf_im_lib::expand_tilde(config.path.clone().unwrap().as_path()),
+        options.wait_for_lock,
+    )
+    .await?;
+
+    {
+        let _step = Step::start("disk_space");
+        disk_space::check_disk_space(
+            &config,
+            &options.mirrors,
+            Some(&python),
+            options.ignore_disk_space,
         )
-        .into_iter()
-        .map(|p| {
-            if std::env::consts::OS == "windows" {
-                idf_im_lib::replace_unescaped_spaces_win(&p)
-            } else {
-                p
-            }
-        })
-        .collect();
+        .await?;
+    }
 
-        idf_im_lib::single_version_post_install(
-            &version_instalation_path.to_str().unwrap(),
-            &idf_path.to_str().unwrap(),
+    // Multiple version starts here
+
+    // from now on an interrupt stops the installation between steps
+    let installing = interrupt::Installing::start();
+    for idf_version in config.idf_versions.clone().unwrap() {
+        interrupt::check()?;
+        logging::set_context("version", &idf_version);
+        let install_path = idf_im_lib::expand_tilde(config.path.clone().unwrap().as_path());
+        let version_instalation_path = install_path.join(&idf_version);
+        if InstallState::is_complete(&version_instalation_path) {
+            info!(
+                "{}",
+                t!(
+                    "wizard.version.installed",
+                    version = &idf_version,
+                    path = version_instalation_path.display()
+                )
+            );
+            config.idf_path = Some(version_instalation_path.join("esp-idf"));
+            continue;
+        }
+
+        let mut transaction =
+            VersionTransaction::begin(&install_path, &idf_version, options.keep_failed)?;
+        match install_version(
+            &mut config,
             &idf_version,
-            &tool_install_directory.to_str().unwrap(),
-            export_paths,
+            &mut transaction,
+            options,
+            &python,
         )
+        .await
+        {
+            Ok(_) => transaction.commit()?,
+            Err(err) => {
+                transaction.rollback(interrupt::requested());
+                return Err(err);
+            }
+        }
     }
-    save_config_if_desired(&config)?;
+    drop(installing);
+    logging::clear_context("version");
+    save_config_if_desired(&config, &python)?;
     let ide_conf_path_tmp = PathBuf::from(&config.esp_idf_json_path.clone().unwrap_or_default());
     debug!("IDE configuration path: {}", ide_conf_path_tmp.display());
     match ensure_path(ide_conf_path_tmp.to_str().unwrap()) {
@@ -624,8 +895,13 @@ pub async fn run_wizzard_run(mut config: Settings) -> Result<(), String> {
             return Err(err.to_string());
         }
     }
+    let _ide_conf_lock = if install_lock.covers(&ide_conf_path_tmp) {
+        None
+    } else {
+        Some(DirLock::acquire(&ide_conf_path_tmp, options.wait_for_lock).await?)
+    };
     // TODO: stop using useless param
-    let ide_conf_path = ide_conf_path_tmp.join("esp_ide.json");
+    let ide_conf_path = ide_conf_path_tmp.join(ide_json::IDE_JSON_FILE_NAME);
     match config.save_esp_ide_json(ide_conf_path.to_str().unwrap()) {
         Ok(_) => debug!("IDE configuration saved to: {}", ide_conf_path.display()),
         Err(err) => {
@@ -640,18 +916,26 @@ pub async fn run_wizzard_run(mut config: Settings) -> Result<(), String> {
             println!("{}", t!("wizard.windows.finish_steps.line_2"));
         }
         _ => {
+            let install_path = config.path.clone().unwrap();
             println!("{}", t!("wizard.posix.finish_steps.line_1"));
-            println!("{}", t!("wizard.posix.finish_steps.line_2"));
-            println!("{:?}", config.path.clone().unwrap());
+            println!(
+                "{}",
+                t!(
+                    "wizard.posix.finish_steps.line_2",
+                    path = install_path.display()
+                )
+            );
             println!("{}", t!("wizard.posix.finish_steps.line_3"));
             println!("============================================");
-            println!("{}:", t!("wizard.posix.finish_steps.line_4"));
+            println!("{}", t!("wizard.posix.finish_steps.line_4"));
             for idf_version in config.idf_versions.clone().unwrap() {
+                let script = install_path.join(format!("activate_idf_{}.sh", idf_version));
                 println!(
-                    "       {} \"{}/{}\"",
-                    t!("wizard.posix.finish_steps.line_5"),
-                    config.path.clone().unwrap().to_str().unwrap(),
-                    format!("activate_idf_{}.sh", idf_version),
+                    "       {}",
+                    t!(
+                        "wizard.posix.finish_steps.line_5",
+                        script = script.display()
+                    )
                 );
             }
             println!("============================================");
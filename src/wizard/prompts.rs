@@ -1,4 +1,5 @@
-use std::path::PathBuf;
+use std::fs;
+use std::path::{Path, PathBuf};
 
 pub use crate::wizard::helpers;
 use helpers::{
@@ -6,10 +7,13 @@ use helpers::{
 };
 use idf_im_lib::settings::Settings;
 use idf_im_lib::system_dependencies;
-use log::{debug, info};
+use log::{debug, info, warn};
 use rust_i18n::t;
 
 use self::helpers::generic_confirm_with_default;
+use crate::configuration;
+use crate::packages::{self, Privileges};
+use crate::python::{self, Interpreter};
 
 pub async fn select_target() -> Result<Vec<String>, String> {
     let mut available_targets = idf_im_lib::idf_versions::get_avalible_targets().await?;
@@ -43,7 +47,10 @@ fn check_prerequisites() -> Result<Vec<String>, String> {
                 debug!("{}", t!("prerequisites.ok"));
                 Ok(vec![])
             } else {
-                info!("{} {:?}", t!("prerequisites.missing"), prerequisites);
+                info!(
+                    "{}",
+                    t!("prerequisites.missing", l = prerequisites.join(", "))
+                );
                 Ok(prerequisites.into_iter().map(|p| p.to_string()).collect())
             }
         }
@@ -78,22 +85,12 @@ pub fn check_and_install_prerequisites(
             if res.map_err(|e| e.to_string())? {
                 system_dependencies::install_prerequisites(unsatisfied_prerequisites)
                     .map_err(|e| e.to_string())?;
-
-                let remaining_prerequisites = run_with_spinner(check_prerequisites)?;
-                if !remaining_prerequisites.is_empty() {
-                    return Err(format!(
-                        "{}",
-                        t!(
-                            "prerequisites.install.catastrophic",
-                            l = remaining_prerequisites.join(", ")
-                        ),
-                    ));
-                } else {
-                    info!("{}", t!("prerequisites.ok"));
-                }
+                check_installed_prerequisites()?;
             } else {
                 return Err(t!("prerequisites.install.ask").to_string());
             }
+        } else if std::env::consts::OS == "linux" {
+            install_linux_prerequisites(&unsatisfied_prerequisites, install_all_prerequisites)?;
         } else {
             return Err(t!("prerequisites.install.ask").to_string());
         }
@@ -104,6 +101,68 @@ pub fn check_and_install_prerequisites(
     Ok(())
 }
 
+fn check_installed_prerequisites() -> Result<(), String> {
+    let remaining_prerequisites = run_with_spinner(check_prerequisites)?;
+    if !remaining_prerequisites.is_empty() {
+        Err(format!(
+            "{}",
+            t!(
+                "prerequisites.install.catastrophic",
+                l = remaining_prerequisites.join(", ")
+            ),
+        ))
+    } else {
+        info!("{}", t!("prerequisites.ok"));
+        Ok(())
+    }
+}
+
+/// Shows the command installing the prerequisites with the package manager
+/// of the distribution, and runs it with `-a` when it doesn't need a
+/// password.
+fn install_linux_prerequisites(prerequisites: &[String], install: bool) -> Result<(), String> {
+    let Some(manager) = packages::detect() else {
+        return Err(t!("prerequisites.install.ask").to_string());
+    };
+    let commands = manager.install_commands(prerequisites);
+    if commands.is_empty() {
+        // the missing prerequisites come with other packages
+        return check_installed_prerequisites();
+    }
+    let command_line = packages::command_line(&commands);
+    if !install {
+        info!(
+            "{}",
+            t!(
+                "prerequisites.install.command",
+                distribution = &manager.distribution().name,
+                command = &command_line
+            )
+        );
+        return Err(t!("prerequisites.install.ask").to_string());
+    }
+    if manager.privileges() == Privileges::None {
+        warn!(
+            "{}",
+            t!(
+                "prerequisites.install.no_privileges",
+                command = &command_line
+            )
+        );
+        return Err(t!("prerequisites.install.ask").to_string());
+    }
+    info!(
+        "{}",
+        t!("prerequisites.install.running", command = &command_line)
+    );
+    for command in &commands {
+        manager
+            .run(command)
+            .map_err(|err| format!("{}: {}", t!("prerequisites.install.failure"), err))?;
+    }
+    check_installed_prerequisites()
+}
+
 fn python_sanity_check(python: Option<&str>) -> Result<(), String> {
     let outpusts = idf_im_lib::python_utils::python_sanity_check(python);
     let mut all_ok = true;
@@ -123,57 +182,147 @@ fn python_sanity_check(python: Option<&str>) -> Result<(), String> {
         Err(t!("python.sanitycheck.fail").to_string())
     }
 }
+
+fn min_python_version() -> String {
+    format!("{}.{}", python::MIN_VERSION.0, python::MIN_VERSION.1)
+}
+
+/// Interpreters passing the sanity check, logging why the others are not
+/// used.
+fn usable_interpreters(interpreters: Vec<Interpreter>) -> Vec<Interpreter> {
+    interpreters
+        .into_iter()
+        .filter(|interpreter| {
+            if !interpreter.is_supported() {
+                info!(
+                    "{}",
+                    t!(
+                        "python.select.skipped_old",
+                        python = interpreter,
+                        min = min_python_version()
+                    )
+                );
+                return false;
+            }
+            let failures: Vec<String> =
+                idf_im_lib::python_utils::python_sanity_check(Some(&interpreter.path_str()))
+                    .into_iter()
+                    .filter_map(Result::err)
+                    .collect();
+            if failures.is_empty() {
+                true
+            } else {
+                debug!("{}: {:?}", interpreter, failures);
+                info!(
+                    "{}",
+                    t!("python.select.skipped_check", python = interpreter)
+                );
+                false
+            }
+        })
+        .collect()
+}
+
+/// The interpreter to install with: the configured one, which has to pass
+/// the sanity check, or one of the usable interpreters found on the system,
+/// asking which one if there are several. Returns its path.
 pub fn check_and_install_python(
     non_interactive: bool,
     install_all_prerequisites: bool,
-) -> Result<(), String> {
+    python: Option<&str>,
+) -> Result<String, String> {
     info!("{}", t!("python.sanitycheck.info"));
-    let check_result = if non_interactive {
-        python_sanity_check(None)
+    if let Some(python) = python {
+        let interpreter = python::resolve(python)?;
+        if !interpreter.is_supported() {
+            warn!(
+                "{}",
+                t!(
+                    "python.select.old",
+                    python = &interpreter,
+                    min = min_python_version()
+                )
+            );
+        }
+        let path = interpreter.path_str();
+        if non_interactive {
+            python_sanity_check(Some(&path))?;
+        } else {
+            run_with_spinner(|| python_sanity_check(Some(&path)))?;
+        }
+        info!("{}", t!("python.sanitycheck.ok"));
+        return Ok(path);
+    }
+
+    let usable = if non_interactive {
+        usable_interpreters(python::discover())
     } else {
-        run_with_spinner(|| python_sanity_check(None))
+        run_with_spinner(|| usable_interpreters(python::discover()))
     };
-    if let Err(err) = check_result {
-        if std::env::consts::OS == "windows" {
-            info!("{}", t!("python.sanitycheck.fail"));
-            let res = if !install_all_prerequisites && !non_interactive {
-                generic_confirm("pythhon.install.prompt")
-            } else if install_all_prerequisites {
-                Ok(true)
-            } else {
-                Ok(false)
-            };
+    let selected = match usable.len() {
+        0 => None,
+        1 => usable.into_iter().next(),
+        _ if non_interactive => {
+            debug!("Non-interactive mode, selecting the first usable Python.");
+            usable.into_iter().next()
+        }
+        _ => {
+            let options: Vec<String> = usable.iter().map(ToString::to_string).collect();
+            let selected = generic_select(
+                "python.select.prompt",
+                &options.iter().map(String::as_str).collect::<Vec<_>>(),
+            )?;
+            usable
+                .into_iter()
+                .find(|interpreter| interpreter.to_string() == selected)
+        }
+    };
+    if let Some(interpreter) = selected {
+        info!("{}", t!("python.select.selected", python = &interpreter));
+        info!("{}", t!("python.sanitycheck.ok"));
+        return Ok(interpreter.path_str());
+    }
 
-            if res.map_err(|e| e.to_string())? {
-                system_dependencies::install_prerequisites(vec!["python@3.11.5".to_string()])
-                    .map_err(|e| e.to_string())?;
-                let scp = system_dependencies::get_scoop_path();
-                let usable_python = match scp {
-                    Some(path) => {
-                        let mut python_path = PathBuf::from(path);
-                        python_path.push("python3.exe");
-                        python_path
-                            .to_str()
-                            .map(|s| s.to_string())
-                            .ok_or_else(|| "Unable to convert path to string".to_string())?
-                    }
-                    None => "python3.exe".to_string(),
-                };
-                debug!("Using Python: {}", usable_python);
-                match run_with_spinner(|| python_sanity_check(Some(&usable_python))) {
-                    Ok(_) => info!("{}", t!("python.install.success")),
-                    Err(err) => return Err(format!("{} {:?}", t!("python.install.failure"), err)),
+    if std::env::consts::OS == "windows" {
+        info!("{}", t!("python.sanitycheck.fail"));
+        let res = if !install_all_prerequisites && !non_interactive {
+            generic_confirm("python.install.prompt")
+        } else if install_all_prerequisites {
+            Ok(true)
+        } else {
+            Ok(false)
+        };
+
+        if res.map_err(|e| e.to_string())? {
+            system_dependencies::install_prerequisites(vec!["python@3.11.5".to_string()])
+                .map_err(|e| e.to_string())?;
+            let scp = system_dependencies::get_scoop_path();
+            let usable_python = match scp {
+                Some(path) => {
+                    let mut python_path = PathBuf::from(path);
+                    python_path.push("python3.exe");
+                    python_path
+                        .to_str()
+                        .map(|s| s.to_string())
+                        .ok_or_else(|| "Unable to convert path to string".to_string())?
                 }
-            } else {
-                return Err(t!("python.install.refuse").to_string());
+                None => "python3.exe".to_string(),
+            };
+            debug!("Using Python: {}", usable_python);
+            match run_with_spinner(|| python_sanity_check(Some(&usable_python))) {
+                Ok(_) => info!("{}", t!("python.install.success")),
+                Err(err) => return Err(t!("python.install.failure", error = err).to_string()),
             }
+            Ok(usable_python)
         } else {
-            return Err(format!("{} {:?}", t!("python.sanitycheck.fail"), err));
+            Err(t!("python.install.refuse").to_string())
         }
     } else {
-        info!("{}", t!("python.sanitycheck.ok"))
+        // prints what is wrong with the python3 of PATH, unless it is only
+        // too old
+        python_sanity_check(None)?;
+        Err(t!("python.sanitycheck.fail").to_string())
     }
-    Ok(())
 }
 
 pub fn select_mirrors(mut config: Settings) -> Result<Settings, String> {
@@ -225,7 +374,31 @@ pub fn select_installation_path(mut config: Settings) -> Result<Settings, String
     Ok(config)
 }
 
-pub fn save_config_if_desired(config: &Settings) -> Result<(), String> {
+/// `Settings` has no `python`, so it is added to the saved file, replacing
+/// the one before any table or at the start.
+fn save_python(path: &Path, python: &str) -> Result<(), String> {
+    let content = fs::read_to_string(path)
+        .map_err(|err| format!("Failed to read {}: {}", path.display(), err))?;
+    let setting = format!("python = {}", configuration::toml_string(python));
+    let mut lines = content.lines().map(str::to_string).collect::<Vec<_>>();
+    // the keys after the first table header belong to the tables
+    let top_level = lines
+        .iter()
+        .position(|line| line.trim_start().starts_with('['))
+        .unwrap_or(lines.len());
+    let existing = lines[..top_level].iter().position(|line| {
+        line.split_once('=')
+            .is_some_and(|(key, _)| key.trim().trim_matches('"') == "python")
+    });
+    match existing {
+        Some(index) => lines[index] = setting,
+        None => lines.insert(0, setting),
+    }
+    let content = lines.join("\n") + "\n";
+    fs::write(path, content).map_err(|err| format!("Failed to write {}: {}", path.display(), err))
+}
+
+pub fn save_config_if_desired(config: &Settings, python: &str) -> Result<(), String> {
     let res =
         if config.non_interactive.unwrap_or_default() && config.config_file_save_path.is_some() {
             debug!("Saving config in non-interactive mode.");
@@ -237,9 +410,16 @@ pub fn save_config_if_desired(config: &Settings) -> Result<(), String> {
             generic_confirm_with_default("wizard.after_install.save_config.prompt", true)
         };
     if let Ok(true) = res {
-        config
-            .save()
-            .map_err(|e| format!("{} {:?}", t!("wizard.after_install.config.save_failed"), e))?;
+        config.save().map_err(|e| {
+            t!(
+                "wizard.after_install.config.save_failed",
+                error = format!("{:?}", e)
+            )
+            .to_string()
+        })?;
+        if let Some(path) = &config.config_file_save_path {
+            save_python(path, python)?;
+        }
         println!("{}", t!("wizard.after_install.config.saved"));
     }
     Ok(())
@@ -0,0 +1,183 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use log::{debug, error, info, warn};
+use rust_i18n::t;
+
+/// Versions are prepared here, next to the installed ones, so that moving a
+/// version into place is a rename on the same file system.
+const STAGING_DIR_NAME: &str = ".eim_staging";
+
+/// Installation of one version into `<path>/<version>`.
+///
+/// The steps run in a staging directory which is moved into place with
+/// [`move_into_place`](Self::move_into_place) before the python environment
+/// is set up, as it holds absolute paths. If a step fails, the version is
+/// moved out again, so `<path>/<version>` never keeps a half installed
+/// version and a version which was there before is restored.
+pub struct VersionTransaction {
+    version: String,
+    final_dir: PathBuf,
+    staging_root: PathBuf,
+    staging_dir: PathBuf,
+    backup_dir: Option<PathBuf>,
+    in_place: bool,
+    keep_failed: bool,
+}
+
+/// Where `version` is prepared before it is moved into `install_path`.
+pub fn staging_dir(install_path: &Path, version: &str) -> PathBuf {
+    install_path.join(STAGING_DIR_NAME).join(version)
+}
+
+fn rename(from: &Path, to: &Path) -> Result<(), String> {
+    debug!("Moving {} to {}", from.display(), to.display());
+    fs::rename(from, to).map_err(|err| {
+        format!(
+            "Failed to move {} to {}: {}",
+            from.display(),
+            to.display(),
+            err
+        )
+    })
+}
+
+impl VersionTransaction {
+    /// Prepares the staging directory, reusing the one of an interrupted run.
+    pub fn begin(install_path: &Path, version: &str, keep_failed: bool) -> Result<Self, String> {
+        let staging_root = install_path.join(STAGING_DIR_NAME);
+        let mut transaction = VersionTransaction {
+            version: version.to_string(),
+            final_dir: install_path.join(version),
+            staging_dir: staging_dir(install_path, version),
+            staging_root,
+            backup_dir: None,
+            in_place: false,
+            keep_failed,
+        };
+        fs::create_dir_all(&transaction.staging_dir).map_err(|err| {
+            format!(
+                "Failed to create {}: {}",
+                transaction.staging_dir.display(),
+                err
+            )
+        })?;
+
+        if transaction.final_dir.exists() {
+            let backup_dir = transaction
+                .staging_root
+                .join(format!("{}.previous", version));
+            if backup_dir.exists() {
+                fs::remove_dir_all(&backup_dir).map_err(|err| err.to_string())?;
+            }
+            info!(
+                "{}",
+                t!(
+                    "wizard.transaction.backup",
+                    path = transaction.final_dir.display()
+                )
+            );
+            rename(&transaction.final_dir, &backup_dir)?;
+            transaction.backup_dir = Some(backup_dir);
+        }
+        Ok(transaction)
+    }
+
+    /// Where the version currently is.
+    pub fn current_dir(&self) -> &Path {
+        if self.in_place {
+            &self.final_dir
+        } else {
+            &self.staging_dir
+        }
+    }
+
+    /// Where the version installed before is kept until the transaction
+    /// ends, if there was one.
+    pub fn previous_dir(&self) -> Option<&Path> {
+        self.backup_dir.as_deref()
+    }
+
+    /// Where the version ends up.
+    pub fn final_dir(&self) -> &Path {
+        &self.final_dir
+    }
+
+    /// Moves the staged version to its final directory. Files the post
+    /// install step wrote into the final directory are moved into the
+    /// version first.
+    pub fn move_into_place(&mut self) -> Result<(), String> {
+        if !self.in_place {
+            if self.final_dir.exists() {
+                let entries = fs::read_dir(&self.final_dir).map_err(|err| err.to_string())?;
+                for entry in entries {
+                    let entry = entry.map_err(|err| err.to_string())?;
+                    let staged = self.staging_dir.join(entry.file_name());
+                    if staged.is_dir() {
+                        fs::remove_dir_all(&staged).map_err(|err| err.to_string())?;
+                    } else if staged.exists() {
+                        fs::remove_file(&staged).map_err(|err| err.to_string())?;
+                    }
+                    rename(&entry.path(), &staged)?;
+                }
+                fs::remove_dir(&self.final_dir).map_err(|err| err.to_string())?;
+            }
+            rename(&self.staging_dir, &self.final_dir)?;
+            self.in_place = true;
+        }
+        Ok(())
+    }
+
+    /// The version is installed, the previous one can go.
+    pub fn commit(self) -> Result<(), String> {
+        if let Some(backup_dir) = &self.backup_dir {
+            fs::remove_dir_all(backup_dir)
+                .map_err(|err| format!("Failed to remove {}: {}", backup_dir.display(), err))?;
+        }
+        // only succeeds when no other version is being staged
+        let _ = fs::remove_dir(&self.staging_root);
+        Ok(())
+    }
+
+    /// Moves the version out of `<path>/<version>` and restores the previous
+    /// one. The staging directory is kept when the run was interrupted (so the
+    /// next run resumes) or with `--keep-failed`.
+    pub fn rollback(self, interrupted: bool) {
+        if self.in_place {
+            if let Err(err) = rename(&self.final_dir, &self.staging_dir) {
+                error!("{}", err);
+                return;
+            }
+        }
+        if let Some(backup_dir) = &self.backup_dir {
+            if let Err(err) = rename(backup_dir, &self.final_dir) {
+                error!("{}", err);
+            }
+        }
+
+        if interrupted {
+            return;
+        }
+        if self.keep_failed {
+            warn!(
+                "{}",
+                t!(
+                    "wizard.transaction.kept",
+                    version = &self.version,
+                    path = self.staging_dir.display()
+                )
+            );
+            return;
+        }
+        match fs::remove_dir_all(&self.staging_dir) {
+            Ok(_) => {
+                let _ = fs::remove_dir(&self.staging_root);
+                info!(
+                    "{}",
+                    t!("wizard.transaction.rolled_back", version = &self.version)
+                );
+            }
+            Err(err) => error!("Failed to remove {}: {}", self.staging_dir.display(), err),
+        }
+    }
+}
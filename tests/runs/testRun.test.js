@@ -117,10 +117,18 @@ function testRun(jsonScript) {
                 );
 
             test.data.recursive &&
-                installArgs.push(`-r ${test.data.recursive}`);
+                installArgs.push(
+                    test.data.recursive === "true"
+                        ? "-r"
+                        : "--no-recurse-submodules"
+                );
 
             test.data.nonInteractive &&
-                installArgs.push(`-n ${test.data.nonInteractive}`);
+                installArgs.push(
+                    test.data.nonInteractive === "true"
+                        ? "-n"
+                        : "--no-non-interactive"
+                );
 
             const pathToIDFScript =
                 os.platform() !== "win32"
@@ -2,6 +2,11 @@ import { expect } from "chai";
 import { describe, it, before, after, beforeEach, afterEach } from "mocha";
 import { InteractiveCLITestRunner } from "../classes/CLITestRunner.class.js";
 import logger from "../classes/logger.class.js";
+import { execFileSync, spawnSync } from "child_process";
+import fs from "fs";
+import os from "os";
+import path from "path";
+import { pathToFileURL } from "url";
 
 export function runArgumentsTests(pathToEim, eimVersion) {
     describe("Basic Arguments Tests ->", function () {
@@ -60,5 +65,302 @@ export function runArgumentsTests(pathToEim, eimVersion) {
                 "Missing error when sending non-existing argument"
             ).to.be.true;
         });
+
+        it("should accept legacy boolean values with a deprecation warning", async function () {
+            logger.info(`Starting test - legacy boolean switches`);
+            await testRunner.start();
+            testRunner.sendInput(`${pathToEim} -n true -r false config show\r`);
+            const deprecated = await testRunner.waitForOutput(
+                "use `--no-recurse-submodules` instead"
+            );
+            expect(
+                deprecated,
+                "Missing deprecation warning for legacy boolean value"
+            ).to.be.true;
+            expect(
+                testRunner.output,
+                "Legacy boolean value not applied"
+            ).to.include("recurse_submodules = false");
+        });
+
+        it("should list outdated versions against a local versions index", function () {
+            logger.info(`Starting test - outdated versions`);
+            const tempDir = fs.mkdtempSync(path.join(os.tmpdir(), "eim-outdated-"));
+            const installation = (name) => ({
+                name,
+                id: name,
+                path: path.join(tempDir, name),
+            });
+            fs.writeFileSync(
+                path.join(tempDir, "esp_ide.json"),
+                JSON.stringify({
+                    idfInstalled: ["v5.3.1", "v5.4", "v4.4.8", "master"].map(
+                        installation
+                    ),
+                })
+            );
+            const versionsIndex = path.join(tempDir, "idf_versions.json");
+            fs.writeFileSync(
+                versionsIndex,
+                JSON.stringify({
+                    VERSIONS: [
+                        { name: "latest" },
+                        { name: "v5.4" },
+                        { name: "v5.3.2" },
+                        { name: "v5.3.1" },
+                        { name: "v5.5-rc1", pre_release: true },
+                        { name: "v4.4.8", end_of_life: true },
+                    ],
+                })
+            );
+
+            const output = execFileSync(pathToEim, [
+                "--esp-idf-json-path",
+                tempDir,
+                "outdated",
+                "--json",
+                "--versions-index",
+                versionsIndex,
+            ]).toString();
+            fs.rmSync(tempDir, { recursive: true, force: true });
+
+            const reports = Object.fromEntries(
+                JSON.parse(output).map((report) => [report.version, report])
+            );
+            expect(reports["v5.3.1"].status).to.equal("outdated");
+            expect(reports["v5.3.1"].latest_patch).to.equal("v5.3.2");
+            expect(reports["v5.3.1"].newer_releases).to.deep.equal(["v5.4"]);
+            expect(reports["v5.4"].status).to.equal("up_to_date");
+            expect(reports["v4.4.8"].status).to.equal("end_of_life");
+            expect(reports["master"].status).to.equal("unknown");
+        });
+    });
+
+    describe("Mirror Definitions Tests ->", function () {
+        let tempDir = null;
+
+        beforeEach(function () {
+            tempDir = fs.mkdtempSync(path.join(os.tmpdir(), "eim-mirrors-"));
+        });
+
+        afterEach(function () {
+            fs.rmSync(tempDir, { recursive: true, force: true });
+        });
+
+        // runs eim, returning its exit code and everything it printed
+        const runEim = (args) => {
+            const result = spawnSync(pathToEim, args, { encoding: "utf-8" });
+            return {
+                status: result.status,
+                output: `${result.stdout}${result.stderr}`,
+            };
+        };
+
+        const validate = (content) => {
+            const configFile = path.join(tempDir, "config.toml");
+            fs.writeFileSync(configFile, content);
+            return runEim(["config", "validate", configFile]);
+        };
+
+        it("should accept prefix and regex rules", function () {
+            logger.info(`Starting test - valid mirror definition`);
+            const result = validate(
+                [
+                    `idf_mirror = "corp"`,
+                    `mirror = "corp"`,
+                    `[mirrors.corp]`,
+                    `git = [{ regex = '^https://github\\.com/espressif/(.*)$', replace = "https://git.example.com/mirror/$1" }]`,
+                    `tools = [{ prefix = "https://github.com", replace = "https://artifacts.example.com/github" }]`,
+                ].join("\n")
+            );
+            expect(result.status, result.output).to.equal(0);
+            expect(result.output).to.include("is valid");
+        });
+
+        it("should report an invalid regex", function () {
+            logger.info(`Starting test - invalid regex in a mirror rule`);
+            const result = validate(
+                [
+                    `[mirrors.corp]`,
+                    `git = [{ regex = "^https://github.com/(", replace = "https://git.example.com/" }]`,
+                ].join("\n")
+            );
+            expect(result.status).to.not.equal(0);
+            expect(result.output).to.include(
+                "mirrors.corp.git: rule 1: invalid regex"
+            );
+        });
+
+        it("should report a rule with both prefix and regex", function () {
+            logger.info(`Starting test - ambiguous mirror rule`);
+            const result = validate(
+                [
+                    `[mirrors.corp]`,
+                    `tools = [{ prefix = "https://github.com", regex = "^https://github.com", replace = "https://artifacts.example.com" }]`,
+                ].join("\n")
+            );
+            expect(result.status).to.not.equal(0);
+            expect(result.output).to.include(
+                "mirrors.corp.tools: rule 1: a rule needs either `prefix` or `regex`"
+            );
+        });
+
+        it("should report a rule without replacement", function () {
+            logger.info(`Starting test - mirror rule without replace`);
+            const result = validate(
+                [
+                    `[mirrors.corp]`,
+                    `tools = [{ prefix = "https://github.com", replace = "https://a.example.com" }, { prefix = "https://github.com" }]`,
+                ].join("\n")
+            );
+            expect(result.status).to.not.equal(0);
+            expect(result.output).to.include(
+                "mirrors.corp.tools: rule 2: the rule has no `replace`"
+            );
+        });
+
+        it("should report unknown keys and rules which are not a list", function () {
+            logger.info(`Starting test - malformed mirror definition`);
+            const result = validate(
+                [
+                    `[mirrors.corp]`,
+                    `git = "https://git.example.com"`,
+                    `submodules = []`,
+                ].join("\n")
+            );
+            expect(result.status).to.not.equal(0);
+            expect(result.output).to.include(
+                "mirrors.corp.git: expected a list of rules"
+            );
+            expect(result.output).to.include(
+                "mirrors.corp.submodules: unknown key, expected `tools` or `git`"
+            );
+        });
+
+        it("should report a mirror which is not defined", function () {
+            logger.info(`Starting test - undefined mirror`);
+            const result = validate(`idf_mirror = "corp"`);
+            expect(result.status).to.not.equal(0);
+            expect(result.output).to.include(
+                "idf_mirror: `corp` is neither a valid http(s) URL nor the name of a mirror"
+            );
+        });
+
+        it("should clone submodules with relative URLs through a regex rule", function () {
+            this.timeout(60000);
+            logger.info(`Starting test - submodules through a git mirror`);
+            const git = (cwd, ...args) =>
+                execFileSync(
+                    "git",
+                    [
+                        "-c",
+                        "user.name=eim",
+                        "-c",
+                        "user.email=eim@example.com",
+                        ...args,
+                    ],
+                    { cwd, encoding: "utf-8" }
+                ).trim();
+            // the mirror holds the repositories with the layout of GitHub
+            const remote = path.join(tempDir, "remote", "espressif");
+            const work = path.join(tempDir, "work");
+            const commits = {};
+            for (const name of ["esp-phy-lib", "esp32-bt-lib"]) {
+                const dir = path.join(work, name);
+                fs.mkdirSync(dir, { recursive: true });
+                git(dir, "init", "-q");
+                fs.writeFileSync(path.join(dir, "README"), name);
+                git(dir, "add", "-A");
+                git(dir, "commit", "-qm", "init");
+                commits[name] = git(dir, "rev-parse", "HEAD");
+                git(
+                    work,
+                    "clone",
+                    "-q",
+                    "--bare",
+                    dir,
+                    path.join(remote, `${name}.git`)
+                );
+            }
+            const idf = path.join(work, "esp-idf");
+            fs.mkdirSync(path.join(idf, "tools"), { recursive: true });
+            git(idf, "init", "-q");
+            fs.writeFileSync(
+                path.join(idf, "tools", "tools.json"),
+                JSON.stringify({ version: 2, tools: [] })
+            );
+            git(idf, "add", "-A");
+            git(idf, "commit", "-qm", "v5.3.1");
+            git(idf, "tag", "-a", "v5.3.1", "-m", "v5.3.1");
+            // one relative and one absolute URL, both have to reach the mirror
+            fs.writeFileSync(
+                path.join(idf, ".gitmodules"),
+                [
+                    `[submodule "components/esp_phy/lib"]`,
+                    `\tpath = components/esp_phy/lib`,
+                    `\turl = ../../espressif/esp-phy-lib.git`,
+                    `[submodule "components/bt/lib"]`,
+                    `\tpath = components/bt/lib`,
+                    `\turl = https://github.com/espressif/esp32-bt-lib.git`,
+                ].join("\n")
+            );
+            const addSubmodule = (name, submodulePath) =>
+                git(
+                    idf,
+                    "update-index",
+                    "--add",
+                    "--cacheinfo",
+                    `160000,${commits[name]},${submodulePath}`
+                );
+            addSubmodule("esp-phy-lib", "components/esp_phy/lib");
+            addSubmodule("esp32-bt-lib", "components/bt/lib");
+            git(idf, "add", ".gitmodules");
+            git(idf, "commit", "-qm", "v5.3.2");
+            git(idf, "tag", "-a", "v5.3.2", "-m", "v5.3.2");
+            git(
+                work,
+                "clone",
+                "-q",
+                "--bare",
+                idf,
+                path.join(remote, "esp-idf.git")
+            );
+
+            // an installed v5.3.1, updated to v5.3.2 with the submodules
+            const installPath = path.join(tempDir, "install");
+            const installed = path.join(installPath, "v5.3.1", "esp-idf");
+            git(tempDir, "clone", "-q", path.join(remote, "esp-idf.git"), installed);
+            git(
+                installed,
+                "-c",
+                "advice.detachedHead=false",
+                "checkout",
+                "-q",
+                "v5.3.1"
+            );
+            const mirrorUrl = pathToFileURL(remote).href;
+            const configFile = path.join(tempDir, "config.toml");
+            fs.writeFileSync(
+                configFile,
+                [
+                    `path = '${installPath}'`,
+                    `esp_idf_json_path = '${path.join(tempDir, "ide")}'`,
+                    `idf_mirror = "local"`,
+                    `non_interactive = true`,
+                    `target = ["esp32"]`,
+                    `[mirrors.local]`,
+                    `git = [{ regex = '^https://github\\.com/espressif/(.*)$', replace = '${mirrorUrl}/$1' }]`,
+                ].join("\n")
+            );
+
+            // the update itself stops later, there is no idf_tools.py
+            const result = runEim(["update", "v5.3.1", "-c", configFile]);
+            expect(result.output).to.include(
+                `Submodule components/esp_phy/lib is cloned from the mirror ${mirrorUrl}/esp-phy-lib.git`
+            );
+            expect(result.output).to.include(
+                `Submodule components/bt/lib is cloned from the mirror ${mirrorUrl}/esp32-bt-lib.git`
+            );
+        });
     });
 }
@@ -4,6 +4,7 @@ import { InteractiveCLITestRunner } from "../classes/CLITestRunner.class.js";
 import logger from "../classes/logger.class.js";
 import os from "os";
 import path from "path";
+import fs from "fs";
 
 /**
  * Setup the following environmental variables to execute this test:
@@ -83,6 +84,86 @@ describe("Check if prerequisites are installed ->", function () {
         }
     );
 
+    /** Linux Specific Tests
+     * Tests below run eim again with the distribution read from a fixture
+     * and the install commands recorded by the fake package manager.
+     */
+
+    (os.platform() === "linux" ? describe : describe.skip)(
+        "Pre-Requisites install command on Linux",
+        function () {
+            const osRelease = path.join(os.tmpdir(), "eim-os-release");
+            const commandLog = path.join(os.tmpdir(), "eim-package-manager.log");
+
+            before(function () {
+                fs.writeFileSync(
+                    osRelease,
+                    'NAME="Fedora Linux"\nID=fedora\nPRETTY_NAME="Fedora Linux 40"\n'
+                );
+            });
+
+            after(function () {
+                fs.rmSync(osRelease, { force: true });
+                fs.rmSync(commandLog, { force: true });
+            });
+
+            // the eim started for every test exits on the missing prerequisites
+            beforeEach(async function () {
+                this.timeout(25000);
+                await testRunner.waitForOutput(
+                    "Please install the missing prerequisites",
+                    20000
+                );
+                testRunner.output = "";
+            });
+
+            afterEach(function () {
+                this.timeout(10000);
+                if (this.currentTest.state === "failed") {
+                    logger.info(
+                        `Terminal output on failure: >>\r ${testRunner.output}`
+                    );
+                }
+            });
+
+            it("Should show the install command of the distribution", async function () {
+                logger.info(`Starting test - install command of the distribution`);
+                this.timeout(25000);
+                testRunner.sendInput(
+                    `EIM_OS_RELEASE=${osRelease} ${pathToEim} -n\r`
+                );
+                const installCommand = await testRunner.waitForOutput(
+                    "dnf install -y",
+                    20000
+                );
+                expect(
+                    installCommand,
+                    "EIM did not show the dnf command installing the prerequisites"
+                ).to.be.true;
+            });
+
+            it("Should run the install command with -a", async function () {
+                logger.info(`Starting test - run the install command`);
+                this.timeout(25000);
+                fs.rmSync(commandLog, { force: true });
+                testRunner.sendInput(
+                    `EIM_OS_RELEASE=${osRelease} EIM_FAKE_PACKAGE_MANAGER=${commandLog} ${pathToEim} -n -a\r`
+                );
+                const installing = await testRunner.waitForOutput(
+                    "Installing the prerequisites",
+                    20000
+                );
+                expect(
+                    installing,
+                    "EIM did not install the missing prerequisites"
+                ).to.be.true;
+                expect(fs.readFileSync(commandLog, "utf8")).to.include(
+                    "dnf install -y"
+                );
+            });
+        }
+    );
+
     /** Windows Specific Tests
      * Tests below will only be executed on win32 platform
      */
@@ -0,0 +1,103 @@
+import { expect } from "chai";
+import { describe, it, before } from "mocha";
+import path from "path";
+import fs from "fs";
+
+// Static checks of locales/app.yml against the sources, no eim binary needed.
+const ROOT = path.join(import.meta.dirname, "..", "..");
+const LOCALE_FILE = path.join(ROOT, "locales", "app.yml");
+const SOURCE_DIR = path.join(ROOT, "src");
+// The wizard passes keys to helpers like generic_confirm("...") instead of t!()
+const WIZARD_DIR = path.join(SOURCE_DIR, "wizard");
+
+function parseLocaleFile(file) {
+    const translations = {};
+    let currentKey = null;
+    for (const line of fs.readFileSync(file, "utf-8").split(/\r?\n/)) {
+        const keyMatch = line.match(/^([A-Za-z_][\w.]*):\s*$/);
+        const valueMatch = line.match(/^\s+([\w-]+):\s*(.*)$/);
+        if (keyMatch) {
+            currentKey = keyMatch[1];
+            translations[currentKey] = {};
+        } else if (valueMatch && currentKey) {
+            translations[currentKey][valueMatch[1]] = valueMatch[2];
+        } else if (line.trim() !== "") {
+            currentKey = null;
+        }
+    }
+    return translations;
+}
+
+function sourceFiles(dir) {
+    return fs.readdirSync(dir, { withFileTypes: true }).flatMap((entry) => {
+        const fullPath = path.join(dir, entry.name);
+        if (entry.isDirectory()) {
+            return sourceFiles(fullPath);
+        }
+        return entry.name.endsWith(".rs") ? [fullPath] : [];
+    });
+}
+
+function usedKeys(namespaces) {
+    const used = new Map();
+    const addKey = (key, file) => {
+        if (!used.has(key)) {
+            used.set(key, path.relative(ROOT, file));
+        }
+    };
+    for (const file of sourceFiles(SOURCE_DIR)) {
+        const content = fs.readFileSync(file, "utf-8");
+        for (const match of content.matchAll(/t!\(\s*"([\w.]+)"/g)) {
+            addKey(match[1], file);
+        }
+        if (file.startsWith(WIZARD_DIR)) {
+            for (const match of content.matchAll(/"([a-z_]+(?:\.\w+)+)"/g)) {
+                if (namespaces.has(match[1].split(".")[0])) {
+                    addKey(match[1], file);
+                }
+            }
+        }
+    }
+    return used;
+}
+
+describe("Translations ->", function () {
+    let translations = null;
+    let languages = null;
+
+    before(function () {
+        translations = parseLocaleFile(LOCALE_FILE);
+        languages = new Set(
+            Object.values(translations).flatMap((t) => Object.keys(t))
+        );
+    });
+
+    it("should translate every key to every language", function () {
+        const missing = [];
+        for (const [key, values] of Object.entries(translations)) {
+            // the `few` plural form only exists in some languages (e.g. cs)
+            if (key.endsWith(".few")) {
+                continue;
+            }
+            for (const language of languages) {
+                if (!values[language]) {
+                    missing.push(`${key} (${language})`);
+                }
+            }
+        }
+        expect(missing, "Keys missing a translation").to.be.empty;
+    });
+
+    it("should define every key used in the sources", function () {
+        const namespaces = new Set(
+            Object.keys(translations).map((key) => key.split(".")[0])
+        );
+        const missing = [];
+        for (const [key, file] of usedKeys(namespaces)) {
+            if (!(key in translations)) {
+                missing.push(`${key} (${file})`);
+            }
+        }
+        expect(missing, "Keys missing in locales/app.yml").to.be.empty;
+    });
+});